@@ -12,7 +12,7 @@ import org.gradle.api.tasks.TaskAction;
 import java.io.IOException;
 import java.net.URISyntaxException;
 import java.nio.file.Files;
-import java.util.HashMap;
+import java.util.ArrayList;
 import java.util.regex.Pattern;
 
 public abstract class ParseWikipedia extends DefaultTask {
@@ -22,11 +22,18 @@ public abstract class ParseWikipedia extends DefaultTask {
     @OutputFile
     public abstract RegularFileProperty getOutput();
 
+    /**
+     * A single line of the json lines output. Colours which are listed more than once keep every entry,
+     * the trainer decides what to do with duplicates
+     */
+    private record Entry(String name, String color) {}
+
     private static final Pattern REGEX = Pattern.compile("\\{\\{Colort/Color.*\\|hex=([^|]+).*\\|name=\\[\\[([^]]+).*}}");
 
     @TaskAction
     public void enact() throws IOException {
-        var output = new HashMap<String, String>();
+        var gson = new Gson();
+        var output = new ArrayList<String>();
 
         for (var file : getInput().get()) {
             var fileContent = Files.readString(file.getAsFile().toPath());
@@ -37,11 +44,10 @@ public abstract class ParseWikipedia extends DefaultTask {
                 if (colourName.contains("|")) {
                     colourName = colourName.split("\\|", 2)[1];
                 }
-                output.put(colourName, matcher.group(1));
+                output.add(gson.toJson(new Entry(colourName, matcher.group(1))));
             }
         }
 
-        var outputJson = new Gson().toJson(output);
-        Files.writeString(getOutput().get().getAsFile().toPath(), outputJson);
+        Files.write(getOutput().get().getAsFile().toPath(), output);
     }
 }
@@ -0,0 +1,47 @@
+package nl.theepicblock.mid.journey.build;
+
+import org.gradle.api.DefaultTask;
+import org.gradle.api.file.DirectoryProperty;
+import org.gradle.api.file.RegularFileProperty;
+import org.gradle.api.provider.Property;
+import org.gradle.api.tasks.*;
+
+import java.io.File;
+import java.io.IOException;
+
+public abstract class TrainTokenizer extends DefaultTask {
+    @InputDirectory
+    public abstract DirectoryProperty getBinary();
+
+    @InputFile
+    public abstract RegularFileProperty getTrainingData();
+
+    @InputFile
+    public abstract RegularFileProperty getConfig();
+
+    @Input
+    public abstract Property<Integer> getVocabSize();
+
+    @OutputFile
+    public abstract RegularFileProperty getOutput();
+
+    @TaskAction
+    public void enact() throws IOException, InterruptedException {
+        var process = new ProcessBuilder()
+                .command(
+                        CargoBuild.getExecutableFromDir(getBinary()),
+                        "--train-tokenizer",
+                        getTrainingData().get().toString(),
+                        getConfig().get().toString(),
+                        getVocabSize().get().toString(),
+                        getOutput().get().toString()
+                )
+                .redirectOutput(new File(getOutput().getAsFile().get() + ".stdout"))
+                .redirectError(new File(getOutput().getAsFile().get() + ".stderr"))
+                .start();
+
+        if (process.waitFor() != 0) {
+            throw new RuntimeException("Failed to run "+getBinary().get());
+        }
+    }
+}
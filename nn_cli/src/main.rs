@@ -2,7 +2,7 @@ use std::{env, fs::File, io::{self, stdout, Write}, path::PathBuf, thread::panic
 
 use crossterm::{cursor, event::{self, read, Event, KeyModifiers}, queue, style::{self, Stylize}, terminal::{disable_raw_mode, enable_raw_mode}, tty::IsTty, ExecutableCommand};
 use futures::executor;
-use trainer::{color::Color, gpu::init_gpu, input::{Config, JsonNetworkParameters}, layer, neural_network::{eval_single, EvalResources}};
+use trainer::{color::Color, gpu::init_gpu, input::{Config, JsonNetworkParameters}, layer, neural_network::{eval_single, EvalResources}, string::string_to_data};
 
 #[tokio::main]
 async fn main() {
@@ -25,7 +25,7 @@ async fn main() {
 
     let gpu = init_gpu().await;
     let parameters = layer::from_json(&parameters, &config, &gpu);
-    let eval_resources = EvalResources::init(&gpu, &config, &parameters, &vec![(vec![0.0; config.input_length() as usize], Color::from_oklab((0.0,0.0,0.0)))]);
+    let eval_resources = EvalResources::init(&gpu, &config, &parameters, &vec![(string_to_data("", &config), Color::from_oklab((0.0,0.0,0.0)), String::new())]);
 
 
     // Setup the box™
@@ -5,10 +5,380 @@ import com.google.gson.FieldNamingPolicy;
 import com.google.gson.GsonBuilder;
 
 import java.io.Reader;
+import java.nio.charset.StandardCharsets;
+import java.util.ArrayList;
+import java.util.Arrays;
+import java.util.List;
+import java.util.Locale;
+import java.util.Map;
+import java.util.regex.Pattern;
+
+public record NNConfig(int inputLength, int[] layers, Normalizer normalize, Alphabet alphabet, Layout layout, HashedFeatures hashedFeatures) {
+    public NNConfig {
+        if (normalize == null) {
+            normalize = Normalizer.DEFAULT;
+        }
+        if (alphabet == null) {
+            alphabet = Alphabet.DEFAULT;
+        }
+        if (layout == null) {
+            layout = Layout.DEFAULT;
+        }
+    }
 
-public record NNConfig(int inputLength, int[] layers) {
     public static NNConfig load(Reader stream) {
         var gson = new GsonBuilder().setFieldNamingPolicy(FieldNamingPolicy.LOWER_CASE_WITH_UNDERSCORES).create();
         return gson.fromJson(stream, NNConfig.class);
     }
+
+    /**
+     * Cleans up a name before it's encoded.
+     * This logic MUST match the one in trainer/src/normalize.rs
+     */
+    public record Normalizer(String qualifiers, Map<String, String> qualifierMap, boolean unescapeEntities, boolean asciiPunctuation, String stripCharacters) {
+        public static final Normalizer DEFAULT = new Normalizer("keep", Map.of(), false, false, "");
+
+        public Normalizer {
+            if (qualifiers == null) {
+                qualifiers = DEFAULT.qualifiers;
+            }
+            if (qualifierMap == null) {
+                qualifierMap = DEFAULT.qualifierMap;
+            }
+            if (stripCharacters == null) {
+                stripCharacters = DEFAULT.stripCharacters;
+            }
+        }
+
+        /**
+         * Applies every step in order: entities, qualifiers, punctuation, stripping, and finally collapsing whitespace
+         */
+        public String normalize(String str) {
+            if (unescapeEntities) {
+                str = unescapeEntities(str);
+            }
+            if (!qualifiers.equals("keep") || !qualifierMap.isEmpty()) {
+                str = replaceQualifiers(str);
+            }
+            if (asciiPunctuation) {
+                str = str.codePoints().map(Normalizer::asciiPunctuation).collect(StringBuilder::new, StringBuilder::appendCodePoint, StringBuilder::append).toString();
+            }
+            if (!stripCharacters.isEmpty()) {
+                str = str.codePoints().filter(c -> stripCharacters.codePoints().noneMatch(s -> s == c)).collect(StringBuilder::new, StringBuilder::appendCodePoint, StringBuilder::append).toString();
+            }
+            return String.join(" ", Arrays.stream(str.split("(?U)\\s+")).filter(w -> !w.isEmpty()).toList());
+        }
+
+        private String replaceQualifiers(String str) {
+            var output = new StringBuilder();
+            var i = 0;
+            int start;
+            while ((start = str.indexOf('(', i)) != -1) {
+                // An unclosed parenthesis isn't a qualifier
+                var end = closingParen(str, start);
+                if (end == -1) {
+                    break;
+                }
+                output.append(str, i, start);
+                var inner = str.substring(start + 1, end);
+                var replacement = qualifierMap.get(inner.trim().toLowerCase(Locale.ROOT));
+                if (replacement == null) {
+                    replacement = switch (qualifiers) {
+                        case "keep" -> null;
+                        case "strip" -> "";
+                        case "unwrap" -> inner;
+                        default -> throw new IllegalArgumentException("Unknown qualifier handling " + qualifiers);
+                    };
+                }
+                if (replacement != null) {
+                    // Keep the replacement from sticking to the words around it
+                    output.append(' ').append(replacement).append(' ');
+                } else {
+                    output.append(str, start, end + 1);
+                }
+                i = end + 1;
+            }
+            output.append(str, i, str.length());
+            return output.toString();
+        }
+
+        private static int closingParen(String str, int start) {
+            var depth = 0;
+            for (int i = start; i < str.length(); i++) {
+                var c = str.charAt(i);
+                if (c == '(') {
+                    depth++;
+                } else if (c == ')') {
+                    depth--;
+                    if (depth == 0) {
+                        return i;
+                    }
+                }
+            }
+            return -1;
+        }
+
+        private static String unescapeEntities(String str) {
+            var output = new StringBuilder();
+            var i = 0;
+            int start;
+            while ((start = str.indexOf('&', i)) != -1) {
+                output.append(str, i, start);
+                var end = str.indexOf(';', start);
+                var decoded = end == -1 ? -1 : decodeEntity(str.substring(start + 1, end));
+                if (decoded != -1) {
+                    output.appendCodePoint(decoded);
+                    i = end + 1;
+                } else {
+                    output.append('&');
+                    i = start + 1;
+                }
+            }
+            output.append(str, i, str.length());
+            return output.toString();
+        }
+
+        /**
+         * @return the decoded code point, or -1 if this isn't a valid entity
+         */
+        private static int decodeEntity(String name) {
+            if (name.startsWith("#")) {
+                int code;
+                try {
+                    if (name.startsWith("#x") || name.startsWith("#X")) {
+                        code = Integer.parseUnsignedInt(name.substring(2), 16);
+                    } else {
+                        code = Integer.parseUnsignedInt(name.substring(1));
+                    }
+                } catch (NumberFormatException e) {
+                    return -1;
+                }
+                // Should match rust's char::from_u32
+                if (!Character.isValidCodePoint(code) || (code >= 0xD800 && code <= 0xDFFF)) {
+                    return -1;
+                }
+                return code;
+            }
+            return switch (name) {
+                case "amp" -> '&';
+                case "lt" -> '<';
+                case "gt" -> '>';
+                case "quot" -> '"';
+                case "apos" -> '\'';
+                case "nbsp" -> '\u00A0';
+                default -> -1;
+            };
+        }
+
+        private static int asciiPunctuation(int c) {
+            return switch (c) {
+                case '\u2018', '\u2019', '\u201B', '\u2032' -> '\'';
+                case '\u201C', '\u201D', '\u201E', '\u2033' -> '"';
+                case '\u2010', '\u2011', '\u2012', '\u2013', '\u2014', '\u2212' -> '-';
+                case '\u2026' -> '.';
+                default -> c;
+            };
+        }
+    }
+
+    /**
+     * Decides which input node each character activates.
+     * This logic MUST match the one in trainer/src/string.rs
+     */
+    public record Alphabet(String letters, boolean foldDiacritics, boolean digits, boolean punctuation) {
+        public static final String DEFAULT_LETTERS = "abcdefghijklmnopqrstuvwxyz";
+        public static final Alphabet DEFAULT = new Alphabet(DEFAULT_LETTERS, false, false, false);
+        private static final Pattern WHITESPACE = Pattern.compile("(?U)\\s");
+
+        public Alphabet {
+            if (letters == null) {
+                letters = DEFAULT_LETTERS;
+            }
+        }
+
+        /**
+         * The amount of nodes for each character position
+         */
+        public int size() {
+            var size = (int)letters.codePoints().count();
+            if (digits) {
+                size += 10;
+            }
+            if (punctuation) {
+                size += 1;
+            }
+            // The unknown node
+            return size + 1;
+        }
+
+        public String normalize(String str) {
+            if (foldDiacritics) {
+                return java.text.Normalizer.normalize(str, java.text.Normalizer.Form.NFKD).replaceAll("\\p{M}", "");
+            } else {
+                return str;
+            }
+        }
+
+        /**
+         * @return the node of the character within its position, or -1 if it shouldn't activate anything
+         */
+        public int charToNum(int c) {
+            // Should match rust's char::is_whitespace, Character.isWhitespace uses a different definition
+            if (WHITESPACE.matcher(Character.toString(c)).matches()) {
+                return -1;
+            }
+            c = Character.toLowerCase(c);
+
+            var letterIndex = letters.codePoints().boxed().toList().indexOf(c);
+            if (letterIndex != -1) {
+                return letterIndex;
+            }
+            var next = (int)letters.codePoints().count();
+            if (digits) {
+                if (c >= '0' && c <= '9') {
+                    return next + (c - '0');
+                }
+                next += 10;
+            }
+            if (punctuation) {
+                if (c < 128 && isAsciiPunctuation(c)) {
+                    return next;
+                }
+                next += 1;
+            }
+            return next;
+        }
+
+        private static boolean isAsciiPunctuation(int c) {
+            return (c >= '!' && c <= '/') || (c >= ':' && c <= '@') || (c >= '[' && c <= '`') || (c >= '{' && c <= '~');
+        }
+    }
+
+    /**
+     * Decides which character position each character goes in.
+     * This logic MUST match the one in trainer/src/string.rs
+     */
+    public record Layout(String strategy, int slotLength) {
+        public static final Layout DEFAULT = new Layout("head_and_last_word", 0);
+
+        public Layout {
+            if (strategy == null) {
+                strategy = DEFAULT.strategy;
+            }
+        }
+
+        /**
+         * @return pairs of {position, code point}
+         */
+        public List<int[]> layout(String input, int length) {
+            var words = new ArrayList<>(Arrays.stream(input.split("(?U)\\s+")).filter(w -> !w.isEmpty()).toList());
+            var output = new ArrayList<int[]>();
+            switch (strategy) {
+                case "head_and_last_word" -> {
+                    var lastWordI = -1;
+                    for (int i = 0; i < words.size(); i++) {
+                        if (!words.get(i).startsWith("(")) {
+                            lastWordI = i;
+                        }
+                    }
+                    var lastWord = lastWordI == -1 ? new int[0] : words.remove(lastWordI).codePoints().toArray();
+                    var leading = String.join(" ", words).codePoints().toArray();
+
+                    // If they don't fit, the last word is preferred and characters are dropped from the middle
+                    var lastWordLength = Math.min(lastWord.length, length);
+                    var leadingLength = Math.min(leading.length, length - lastWordLength);
+                    for (int i = 0; i < leadingLength; i++) {
+                        output.add(new int[]{i, leading[i]});
+                    }
+                    for (int i = 0; i < lastWordLength; i++) {
+                        output.add(new int[]{length - i - 1, lastWord[i]});
+                    }
+                }
+                case "left" -> {
+                    var chars = String.join(" ", words).codePoints().toArray();
+                    for (int i = 0; i < Math.min(chars.length, length); i++) {
+                        output.add(new int[]{i, chars[i]});
+                    }
+                }
+                case "word_slots" -> {
+                    var slots = length / slotLength;
+                    for (int slot = 0; slot < Math.min(words.size(), slots); slot++) {
+                        var chars = words.get(slot).codePoints().toArray();
+                        for (int i = 0; i < Math.min(chars.length, slotLength); i++) {
+                            output.add(new int[]{slot * slotLength + i, chars[i]});
+                        }
+                    }
+                }
+                case "bag_of_words" -> {
+                    for (var word : words) {
+                        var chars = word.codePoints().toArray();
+                        for (int i = 0; i < chars.length && i < length && output.size() < length; i++) {
+                            output.add(new int[]{i, chars[i]});
+                        }
+                    }
+                }
+                default -> throw new IllegalArgumentException("Unknown layout strategy " + strategy);
+            }
+            return output;
+        }
+    }
+
+    /**
+     * An extra block of input nodes which doesn't depend on where things are in the name.
+     * May be null, in which case there are no hashed features.
+     * This logic MUST match the one in trainer/src/string.rs
+     */
+    public record HashedFeatures(int buckets, int[] ngramLengths, Boolean words, Integer maxActive) {
+        public HashedFeatures {
+            if (ngramLengths == null) {
+                ngramLengths = new int[]{3};
+            }
+            if (words == null) {
+                words = true;
+            }
+            if (maxActive == null) {
+                maxActive = 32;
+            }
+        }
+
+        /**
+         * @return the buckets activated by the string
+         */
+        public List<Integer> buckets(String input) {
+            var output = new ArrayList<Integer>();
+            var features = new ArrayList<String>();
+            for (var word : input.toLowerCase(Locale.ROOT).split("(?U)\\s+")) {
+                if (word.isEmpty()) {
+                    continue;
+                }
+                var marked = "<" + word + ">";
+                if (words) {
+                    features.add(marked);
+                }
+                var chars = marked.codePoints().toArray();
+                for (var n : ngramLengths) {
+                    for (int i = 0; i + n <= chars.length; i++) {
+                        features.add(new String(chars, i, n));
+                    }
+                }
+            }
+
+            for (var feature : features) {
+                var bucket = Integer.remainderUnsigned(fnv1a(feature), buckets);
+                if (!output.contains(bucket) && output.size() < maxActive) {
+                    output.add(bucket);
+                }
+            }
+            return output;
+        }
+
+        private static int fnv1a(String str) {
+            var hash = 0x811c9dc5;
+            for (var b : str.getBytes(StandardCharsets.UTF_8)) {
+                hash ^= b & 0xff;
+                hash *= 0x01000193;
+            }
+            return hash;
+        }
+    }
 }
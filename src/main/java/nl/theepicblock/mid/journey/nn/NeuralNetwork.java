@@ -1,15 +1,10 @@
 package nl.theepicblock.mid.journey.nn;
 
-import com.google.common.collect.Iterables;
-import joptsimple.internal.Strings;
 import net.fabricmc.api.EnvType;
 import net.fabricmc.api.Environment;
 import nl.theepicblock.mid.journey.MidJourneyClient;
 import nl.theepicblock.mid.journey.OkLab;
 
-import java.util.Arrays;
-import java.util.Objects;
-
 public class NeuralNetwork {
     @Environment(EnvType.CLIENT)
     public static int eval(String input) {
@@ -49,52 +44,27 @@ public class NeuralNetwork {
 
     private static float[] createFirstLayer(String input, NNConfig config) {
         // This logic MUST match the one in trainer/src/string.rs
-        var output = new float[config.inputLength() * 27];
+        var alphabet = config.alphabet();
+        var nodes = alphabet.size();
+        var length = config.inputLength();
+        var hashed = config.hashedFeatures();
+        var output = new float[length * nodes + (hashed == null ? 0 : hashed.buckets())];
+        input = alphabet.normalize(config.normalize().normalize(input));
 
-        var words = input.split("\\s");
-        var lastWordI = -1;
-        for (int i = 0; i < words.length; i++) {
-            if (!words[i].startsWith("(")) {
-                lastWordI = i;
-            }
-        }
-        var lastWord = words[lastWordI];
-        words[lastWordI] = null;
-
-        var chars = Strings.join(Iterables.filter(Arrays.asList(words), Objects::nonNull), " ").chars().iterator();
-        int i = 0;
-        while (chars.hasNext()) {
-            var n = charToNum(chars.next());
+        for (var position : config.layout().layout(input, length)) {
+            var n = alphabet.charToNum(position[1]);
             if (n != -1) {
-                output[i * 27 + n] = 1f;
+                output[position[0] * nodes + n] = 1f;
             }
-            i++;
         }
 
-        chars = lastWord.chars().iterator();
-        i = 0;
-        while (chars.hasNext()) {
-            var n = charToNum(chars.next());
-            if (n != -1) {
-                output[(config.inputLength() - i - 1) * 27 + n] = 1f;
+        // The hashed features come after all character positions
+        if (hashed != null) {
+            for (var bucket : hashed.buckets(input)) {
+                output[length * nodes + bucket] = 1f;
             }
-            i++;
         }
 
         return output;
     }
-
-    private static int charToNum(int n) {
-        // To uppercase
-        if (n >= 'A' && n <= 'Z') {
-            n += 'a' - 'A';
-        }
-        if (n >= 'a' && n <= 'z') {
-            return n - 'a';
-        } else if (n == ' ') {
-            return -1;
-        } else {
-            return 26;
-        }
-    }
 }
@@ -0,0 +1,312 @@
+use std::path::PathBuf;
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{color::Color, layer::MainType};
+
+/// Adds synthetic entries based on the training names: variants with typos, so the network learns to deal with
+/// the way players actually type, and variants with a modifier such as "light" or "dark" in front.
+/// Casing isn't varied, as the alphabet lowercases every character and the variants would encode identically.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Augmentation {
+    /// The average amount of variants with a typo added for each training name. 0 disables them
+    pub typo_rate: f64,
+    /// Swap two neighbouring characters
+    pub swaps: bool,
+    /// Leave out a character
+    pub drops: bool,
+    /// Type a character twice
+    pub duplications: bool,
+    /// Join two words, or split one
+    pub whitespace: bool,
+    /// The average amount of variants with a modifier added for each training name. 0 disables them
+    pub modifier_rate: f64,
+    /// The file containing the modifiers, relative to the config. See `Modifier`
+    pub modifiers: Option<PathBuf>,
+    /// Loaded from `modifiers` by `Config::load`
+    #[serde(skip)]
+    pub modifier_rules: Vec<Modifier>,
+}
+
+/// A word which changes the colour in a predictable way, such as "light" or "dark"
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Modifier {
+    pub word: String,
+    /// Added to the OkLab lightness
+    #[serde(default)]
+    pub lightness: MainType,
+    /// Multiplies the OkLab chroma
+    #[serde(default = "Modifier::default_chroma")]
+    pub chroma: MainType,
+}
+
+/// What kind of augmentation created an entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synthetic {
+    Typo,
+    Modifier,
+}
+
+/// An entry which isn't in the real data
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntheticEntry {
+    pub name: String,
+    pub color: Color,
+    pub kind: Synthetic,
+    /// The index of the name this entry is a variant of
+    pub parent: usize,
+}
+
+impl Default for Augmentation {
+    fn default() -> Self {
+        Self {
+            typo_rate: 0.0,
+            swaps: true,
+            drops: true,
+            duplications: true,
+            whitespace: true,
+            modifier_rate: 0.0,
+            modifiers: None,
+            modifier_rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Typo {
+    Swap,
+    Drop,
+    Duplicate,
+    Whitespace,
+}
+
+impl Augmentation {
+    fn typos(&self) -> Vec<Typo> {
+        [(self.swaps, Typo::Swap), (self.drops, Typo::Drop), (self.duplications, Typo::Duplicate), (self.whitespace, Typo::Whitespace)]
+            .into_iter()
+            .filter(|t| t.0)
+            .map(|t| t.1)
+            .collect()
+    }
+}
+
+/// Creates the extra training entries for the given (training) names
+pub fn augment(names: &[(String, Color)], augmentation: &Augmentation) -> Vec<SyntheticEntry> {
+    let mut output = Vec::new();
+
+    // Different seeds than the split, so enabling augmentation doesn't change which names are used for checking.
+    // Each kind has its own seed as well, so enabling one doesn't change what the other produces
+    let typos = augmentation.typos();
+    if augmentation.typo_rate > 0.0 && !typos.is_empty() {
+        let mut rand = ChaCha20Rng::from_seed([5; 32]);
+        for (parent, (name, color)) in names.iter().enumerate() {
+            for _ in 0..variant_count(augmentation.typo_rate, &mut rand) {
+                let typo = *typos.choose(&mut rand).unwrap();
+                if let Some(variant) = typo.apply(name, &mut rand).filter(|v| v != name) {
+                    output.push(SyntheticEntry { name: variant, color: *color, kind: Synthetic::Typo, parent });
+                }
+            }
+        }
+    }
+
+    let modifiers = &augmentation.modifier_rules;
+    if augmentation.modifier_rate > 0.0 && !modifiers.is_empty() {
+        let mut rand = ChaCha20Rng::from_seed([6; 32]);
+        for (parent, (name, color)) in names.iter().enumerate() {
+            let count = variant_count(augmentation.modifier_rate, &mut rand);
+            // "light dark blue" doesn't make much sense
+            let lowercase = name.to_lowercase();
+            if lowercase.split_whitespace().any(|w| modifiers.iter().any(|m| m.word.to_lowercase() == w)) {
+                continue;
+            }
+            for modifier in modifiers.choose_multiple(&mut rand, count) {
+                output.push(SyntheticEntry {
+                    name: format!("{} {name}", modifier.word),
+                    color: modifier.apply(*color),
+                    kind: Synthetic::Modifier,
+                    parent,
+                });
+            }
+        }
+    }
+
+    return output;
+}
+
+/// `rate` rounded up or down at random, so it's right on average
+pub fn variant_count(rate: f64, rand: &mut impl Rng) -> usize {
+    return rate.floor() as usize + rand.gen_bool(rate.fract()) as usize;
+}
+
+impl Modifier {
+    fn default_chroma() -> MainType {
+        1.0
+    }
+
+    /// Changes the lightness and chroma of the colour. If the result doesn't fit in srgb, the chroma is reduced until it does
+    pub fn apply(&self, color: Color) -> Color {
+        let (l, a, b) = color.to_oklab();
+        let l = (l + self.lightness).clamp(0.0, 1.0);
+        let with_chroma = |scale: MainType| Color::from_oklab((l, a * scale, b * scale));
+
+        if in_gamut(with_chroma(self.chroma)) {
+            return with_chroma(self.chroma);
+        }
+        // Without any chroma the colour is a grey, which always fits
+        let (mut low, mut high) = (0.0, self.chroma);
+        for _ in 0..24 {
+            let mid = (low + high) / 2.0;
+            if in_gamut(with_chroma(mid)) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        return with_chroma(low);
+    }
+}
+
+fn in_gamut(color: Color) -> bool {
+    let (r, g, b) = color.to_linear_srgb();
+    return [r, g, b].iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c));
+}
+
+impl Typo {
+    /// Returns None if the name is too short for this typo
+    fn apply(self, name: &str, rand: &mut impl Rng) -> Option<String> {
+        let mut chars: Vec<char> = name.chars().collect();
+        match self {
+            Typo::Swap => {
+                if chars.len() < 2 {
+                    return None;
+                }
+                let i = rand.gen_range(0..chars.len() - 1);
+                chars.swap(i, i + 1);
+            }
+            Typo::Drop => {
+                if chars.len() < 2 {
+                    return None;
+                }
+                chars.remove(rand.gen_range(0..chars.len()));
+            }
+            Typo::Duplicate => {
+                if chars.is_empty() {
+                    return None;
+                }
+                let i = rand.gen_range(0..chars.len());
+                chars.insert(i, chars[i]);
+            }
+            Typo::Whitespace => {
+                let spaces: Vec<_> = (0..chars.len()).filter(|i| chars[*i].is_whitespace()).collect();
+                if !spaces.is_empty() && rand.gen_bool(0.5) {
+                    chars.remove(*spaces.choose(rand).unwrap());
+                } else {
+                    if chars.len() < 2 {
+                        return None;
+                    }
+                    chars.insert(rand.gen_range(1..chars.len()), ' ');
+                }
+            }
+        }
+        return Some(chars.into_iter().collect());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use crate::{color::Color, input::{Config, RawEntry, TrainingDataRaw}, training_data::process_data};
+
+    use super::{augment, in_gamut, Augmentation, Modifier, Synthetic, Typo};
+
+    /// The modifiers shipped with the mod
+    fn modifiers() -> Vec<Modifier> {
+        let json: String = include_str!("../../modifiers.jsonc").lines().map(|l| l.split("//").next().unwrap()).collect();
+        return serde_json::from_str(&json).unwrap();
+    }
+
+    #[test]
+    fn makes_single_typos() {
+        let mut rand = ChaCha20Rng::from_seed([0; 32]);
+        for _ in 0..100 {
+            let swapped = Typo::Swap.apply("abcd", &mut rand).unwrap();
+            assert!(["bacd", "acbd", "abdc"].contains(&swapped.as_str()), "{swapped}");
+            assert_eq!(Typo::Drop.apply("abcd", &mut rand).unwrap().len(), 3);
+            assert_eq!(Typo::Duplicate.apply("abcd", &mut rand).unwrap().len(), 5);
+            let spaced = Typo::Whitespace.apply("ab cd", &mut rand).unwrap();
+            assert!(spaced == "abcd" || spaced.split(' ').count() == 3, "{spaced}");
+        }
+        assert_eq!(Typo::Swap.apply("a", &mut rand), None);
+        assert_eq!(Typo::Drop.apply("a", &mut rand), None);
+    }
+
+    #[test]
+    fn adds_typo_variants() {
+        let black = Color::from_oklab((0.0, 0.0, 0.0));
+        let names: Vec<_> = ["dark blue", "light green", "red"].iter().map(|n| (n.to_string(), black)).collect();
+        assert!(augment(&names, &Augmentation::default()).is_empty());
+
+        let augmentation = Augmentation { typo_rate: 2.0, ..Default::default() };
+        let variants = augment(&names, &augmentation);
+        // Typos which don't change anything are skipped
+        assert!(variants.len() > 3 && variants.len() <= 6);
+        assert!(variants.iter().all(|v| v.kind == Synthetic::Typo && v.color == black));
+        assert_eq!(variants, augment(&names, &augmentation));
+    }
+
+    #[test]
+    fn adds_modifier_variants() {
+        let blue = Color::from_str("#4060c0").unwrap();
+        let names = vec![("blue".to_owned(), blue), ("Dark green".to_owned(), blue)];
+        let augmentation = Augmentation { modifier_rate: 1.0, modifier_rules: modifiers(), ..Default::default() };
+        let variants = augment(&names, &augmentation);
+
+        // Names which already have a modifier are left alone
+        assert_eq!(variants.len(), 1);
+        assert_eq!(variants[0].kind, Synthetic::Modifier);
+        let word = variants[0].name.strip_suffix(" blue").unwrap();
+        let modifier = augmentation.modifier_rules.iter().find(|m| m.word == word).unwrap();
+        assert_eq!(variants[0].color, modifier.apply(blue));
+    }
+
+    #[test]
+    fn modifiers_change_lightness_and_chroma() {
+        let modifiers = modifiers();
+        let get = |word: &str| modifiers.iter().find(|m| m.word == word).unwrap();
+        let chroma = |c: Color| {
+            let (_, a, b) = c.to_oklab();
+            a.hypot(b)
+        };
+
+        for hex in ["#4060c0", "#ff0000", "#20a040", "#808080", "#000000", "#ffffff"] {
+            let color = Color::from_str(hex).unwrap();
+            assert!(get("light").apply(color).l >= color.l, "{hex}");
+            assert!(get("dark").apply(color).l <= color.l, "{hex}");
+            assert!(chroma(get("pale").apply(color)) <= chroma(color) + 1e-6, "{hex}");
+            for modifier in &modifiers {
+                assert!(in_gamut(modifier.apply(color)), "{} {hex}", modifier.word);
+            }
+        }
+        // Pure red can't get more vivid
+        let red = Color::from_str("#ff0000").unwrap();
+        assert!((chroma(get("vivid").apply(red)) - chroma(red)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn leaves_checking_set_alone() {
+        let raw: TrainingDataRaw = (0..100).map(|i| RawEntry::new(format!("colour number {i}"), "#123456")).collect();
+        let (plain, _) = process_data(raw.clone(), &Config::test(r#"{ "percentage_training": 0.5 }"#));
+        let (augmented, _) = process_data(raw, &Config::test(r#"{ "percentage_training": 0.5, "augmentation": { "typo_rate": 1.0 } }"#));
+
+        assert_eq!(plain.checking, augmented.checking);
+        assert!(plain.synthetic.is_empty());
+        assert!(augmented.synthetic.len() > 50);
+        assert_eq!(augmented.training.len(), plain.training.len() + augmented.synthetic.len());
+        assert_eq!(plain.training, augmented.training[..plain.training.len()]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{error::Error, fmt::Display, str::FromStr};
 
 use bytemuck::{Pod, Zeroable};
 use color_processing::Color as LibColor;
@@ -6,7 +6,7 @@ use color_processing::Color as LibColor;
 use crate::layer::MainType;
 
 /// Represents a colour in the oklab colour space
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
 #[repr(C)]
 pub struct Color {
     pub l: MainType,
@@ -14,6 +14,22 @@ pub struct Color {
     pub b: MainType,
 }
 
+/// A string which isn't a colour
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseColorError {
+    pub value: String,
+    /// What the colour parser had to say about it
+    pub reason: String,
+}
+
+impl Display for ParseColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} is not a colour: {}", self.value, self.reason)
+    }
+}
+
+impl Error for ParseColorError {}
+
 impl From<&[MainType]> for Color {
     fn from(value: &[MainType]) -> Self {
         Self {
@@ -25,8 +41,8 @@ impl From<&[MainType]> for Color {
 }
 
 impl Color {
-    pub fn from_str(str: &str) -> Result<Self, <LibColor as FromStr>::Err> {
-        let rgb = LibColor::from_str(str)?;
+    pub fn from_str(str: &str) -> Result<Self, ParseColorError> {
+        let rgb = LibColor::from_str(str).map_err(|reason| ParseColorError { value: str.to_owned(), reason })?;
         let rgb = (
             rgb.red as MainType / 255 as MainType,
             rgb.green as MainType / 255 as MainType,
@@ -155,6 +171,14 @@ mod test {
         assert!(cdist(oklab, round_trip_oklab) < 0.0000005, "Roundtripping oklab {oklab:?} should result in the same value but gave {round_trip_oklab:?} instead");
     }
 
+    #[test]
+    fn reports_invalid_colors() {
+        assert_eq!(Color::from_str("#ffffff").unwrap(), Color::from_rgb((1.0, 1.0, 1.0)));
+        let error = Color::from_str("#12345g").unwrap_err();
+        assert_eq!(error.value, "#12345g");
+        assert!(error.to_string().contains("#12345g"));
+    }
+
     fn cdist(a: Color, b: Color) -> MainType {
         dist((a.l, a.a, a.b), (b.l, b.a, b.b))
     }
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{cpu::CpuNetwork, input::{Config, JsonNetworkParameters}, layer::{MainType, Size}, string::{string_to_data, NO_INPUT}};
+
+/// Everything needed to check that an implementation encodes names and runs the network exactly like the trainer does.
+/// The model is stored inside the fixture, so the file can be checked on its own
+#[derive(Serialize, Deserialize)]
+pub struct Fixtures {
+    /// The contents of the nn_config the network was trained with
+    pub config: serde_json::Value,
+    pub parameters: JsonNetworkParameters,
+    /// The size of the input vector
+    pub input_size: Size,
+    pub cases: Vec<Case>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Case {
+    pub name: String,
+    /// The indices of the input nodes which are set to one. Every other input is zero
+    pub input: Vec<u32>,
+    /// The activations of each layer, the last one being the output
+    pub layers: Vec<Vec<MainType>>,
+    /// The output as an srgb colour
+    pub hex: String,
+}
+
+/// Runs each name through the cpu implementation and records every intermediate step
+pub fn create(config_json: serde_json::Value, parameters: JsonNetworkParameters, names: Vec<String>) -> Fixtures {
+    let config = Config::deserialize(&config_json).expect("Invalid config");
+    let network = CpuNetwork::new(&config, &parameters);
+
+    let cases = names.into_iter().map(|name| {
+        let input = string_to_data(&name, &config);
+        let layers = network.eval_layers(&input);
+        Case {
+            hex: crate::color::Color::from(layers.last().unwrap().as_slice()).to_hex(),
+            input: input.into_iter().take_while(|n| *n != NO_INPUT).collect(),
+            layers,
+            name,
+        }
+    }).collect();
+
+    Fixtures {
+        input_size: config.input_length(),
+        config: config_json,
+        parameters,
+        cases,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use crate::{color::Color, cpu::CpuNetwork, input::Config, string::{string_to_data, NO_INPUT}};
+
+    use super::Fixtures;
+
+    /// Regenerate with `trainer --fixtures fixtures/model_config.json fixtures/model_parameters.json fixtures/names.json fixtures/conformance.json`
+    const FIXTURES: &str = include_str!("../fixtures/conformance.json");
+
+    #[test]
+    fn matches_stored_fixtures() {
+        let fixtures: Fixtures = serde_json::from_str(FIXTURES).unwrap();
+        let config = Config::deserialize(&fixtures.config).unwrap();
+        let network = CpuNetwork::new(&config, &fixtures.parameters);
+        assert_eq!(config.input_length(), fixtures.input_size);
+
+        for case in &fixtures.cases {
+            let input = string_to_data(&case.name, &config);
+            assert_eq!(input.iter().copied().take_while(|n| *n != NO_INPUT).collect::<Vec<_>>(), case.input, "Input of {:?}", case.name);
+
+            let layers = network.eval_layers(&input);
+            assert_eq!(layers.len(), case.layers.len());
+            for (actual, expected) in layers.iter().zip(&case.layers) {
+                assert_eq!(actual.len(), expected.len());
+                for (a, e) in actual.iter().zip(expected) {
+                    assert!((a - e).abs() <= 1e-5 * e.abs().max(1.0), "Activations of {:?}: {actual:?} != {expected:?}", case.name);
+                }
+            }
+            assert_eq!(Color::from(layers.last().unwrap().as_slice()).to_hex(), case.hex, "Colour of {:?}", case.name);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+
+use crate::{input::{Config, JsonNetworkParameters, LayerConfig}, layer::{dense::JsonDenseLayer, MainType}, string::NO_INPUT, training_data::GpuInputData};
+
+/// Runs the network on the cpu, one input at a time. Much slower than the gpu, but simple enough
+/// to serve as the reference for the shaders and for other implementations, such as the java one.
+/// Should match the `compute_forwards` shaders and `NeuralNetwork.eval` on the java side
+pub struct CpuNetwork {
+    layers: Vec<(LayerConfig, JsonDenseLayer)>,
+}
+
+impl CpuNetwork {
+    pub fn new(config: &Config, parameters: &JsonNetworkParameters) -> Self {
+        let layers = config.layers().into_iter().zip(parameters).map(|(layer, json)| {
+            // Both kinds of layers store their parameters the same way
+            let json = JsonDenseLayer::deserialize(json).expect("Invalid parameters for layer");
+            assert_eq!(json.weights.len() as u64, layer.previous_size * layer.size, "Wrong amount of weights");
+            assert_eq!(json.biases.len() as u64, layer.size, "Wrong amount of biases");
+            (layer, json)
+        }).collect();
+
+        Self { layers }
+    }
+
+    /// Returns the activations of each layer, the last one being the output
+    pub fn eval_layers(&self, input: &GpuInputData) -> Vec<Vec<MainType>> {
+        let mut output: Vec<Vec<MainType>> = Vec::with_capacity(self.layers.len());
+        for (layer, parameters) in &self.layers {
+            let previous_size = layer.previous_size as usize;
+            let values = (0..layer.size as usize).map(|node| {
+                let weights = &parameters.weights[node * previous_size..(node + 1) * previous_size];
+                let mut z = 0.0;
+                match output.last() {
+                    // The input is one-hot, so only the active nodes contribute
+                    None => {
+                        for input_node in input.iter().take_while(|n| **n != NO_INPUT) {
+                            z += weights[*input_node as usize];
+                        }
+                    }
+                    Some(previous) => {
+                        for (a, weight) in previous.iter().zip(weights) {
+                            z += a * weight;
+                        }
+                    }
+                }
+                return activation(z + parameters.biases[node]);
+            }).collect();
+            output.push(values);
+        }
+        return output;
+    }
+}
+
+/// Should match `activation` in lib.wgsl
+fn activation(x: MainType) -> MainType {
+    if x >= 0.0 {
+        return x;
+    } else {
+        return 0.01 * x;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use crate::{color::Color, conformance::Fixtures, gpu::{open_device, request_adapter}, input::Config, layer, neural_network::{eval_single, EvalResources}, string::string_to_data};
+
+    use super::CpuNetwork;
+
+    #[tokio::test]
+    async fn matches_gpu() {
+        let Some(adapter) = request_adapter().await else {
+            eprintln!("No gpu adapter available, skipping test");
+            return;
+        };
+        let gpu = open_device(adapter).await;
+        let fixtures: Fixtures = serde_json::from_str(include_str!("../fixtures/conformance.json")).unwrap();
+        let config = Config::deserialize(&fixtures.config).unwrap();
+
+        let cpu = CpuNetwork::new(&config, &fixtures.parameters);
+        let network = layer::from_json(&fixtures.parameters, &config, &gpu);
+        let resources = EvalResources::init(&gpu, &config, &network, &vec![(string_to_data("", &config), Color::from_oklab((0.0, 0.0, 0.0)), String::new())]);
+
+        for case in &fixtures.cases {
+            let expected = Color::from(cpu.eval_layers(&string_to_data(&case.name, &config)).last().unwrap().as_slice());
+            let actual = eval_single(&case.name, &gpu, &config, &resources).await;
+            for (a, e) in [(actual.l, expected.l), (actual.a, expected.a), (actual.b, expected.b)] {
+                assert!((a - e).abs() <= 1e-4 * e.abs().max(1.0), "{:?}: {actual:?} != {expected:?}", case.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{color::Color, input::{Config, RawEntry, TrainingDataRaw}, layer::MainType, normalize};
+
+/// How to deal with names which occur more than once in the training data.
+/// Names are duplicates if they're the same after normalisation, ignoring case and whitespace
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Duplicates {
+    pub policy: Policy,
+    /// Duplicates with colours further apart than this (in OkLab) are conflicts
+    pub conflict_distance: MainType,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Policy {
+    /// Use the first entry and drop the others
+    KeepFirst,
+    /// Replace the entries by a single one with the average colour
+    Average,
+    /// Train on every entry
+    #[default]
+    KeepAll,
+    /// Refuse to train if there are any conflicts. Duplicates which agree are treated like `KeepFirst`
+    Error,
+}
+
+impl Default for Duplicates {
+    fn default() -> Self {
+        Self {
+            policy: Policy::default(),
+            conflict_distance: 0.05,
+        }
+    }
+}
+
+/// Every group of duplicates that was found
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub policy: Policy,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Group {
+    /// The normalised name the entries share
+    pub key: String,
+    /// Whether the names are exactly the same, rather than only after normalisation
+    pub exact: bool,
+    /// The largest OkLab distance between two of the colours
+    pub distance: MainType,
+    pub conflict: bool,
+    pub entries: Vec<ReportEntry>,
+    /// The colour that's used for training, unless every entry is kept
+    pub result: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReportEntry {
+    pub name: String,
+    pub color: String,
+    pub source: Option<String>,
+}
+
+impl Report {
+    pub fn conflicts(&self) -> usize {
+        return self.groups.iter().filter(|g| g.conflict).count();
+    }
+}
+
+/// Names are compared the way the network sees them, and without any whitespace
+fn key(name: &str, config: &Config) -> String {
+    return normalize::prepare(name, config).chars().filter(|c| !c.is_whitespace()).collect();
+}
+
+/// Finds the duplicates and applies the policy. The order of the entries is kept, merged entries take the place of the first one.
+/// Returns the report as an error if the policy is `Error` and there are conflicts
+pub fn deduplicate(raw: TrainingDataRaw, config: &Config) -> Result<(TrainingDataRaw, Report), Report> {
+    let settings = &config.duplicates;
+
+    // The indices of the entries with the same key, in order of their first entry
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_key = HashMap::new();
+    for (i, entry) in raw.iter().enumerate() {
+        let group = *by_key.entry(key(&entry.name, config)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(i);
+    }
+
+    let mut report = Report { policy: settings.policy, groups: Vec::new() };
+    let mut output = Vec::with_capacity(groups.len());
+    for group in groups {
+        if group.len() == 1 {
+            output.push(raw[group[0]].clone());
+            continue;
+        }
+        if settings.policy == Policy::KeepAll {
+            report.groups.push(report_group(&raw, &group, config, None));
+            continue;
+        }
+
+        let first = &raw[group[0]];
+        let merged = match settings.policy {
+            Policy::Average => {
+                let colors: Vec<_> = group.iter().map(|i| color(&raw[*i]).to_oklab()).collect();
+                let n = colors.len() as MainType;
+                let sum = colors.iter().fold((0.0, 0.0, 0.0), |s, c| (s.0 + c.0, s.1 + c.1, s.2 + c.2));
+                RawEntry { color: to_hex(Color::from_oklab((sum.0 / n, sum.1 / n, sum.2 / n))), ..first.clone() }
+            }
+            _ => first.clone(),
+        };
+        report.groups.push(report_group(&raw, &group, config, Some(merged.color.clone())));
+        output.push(merged);
+    }
+
+    if settings.policy == Policy::Error && report.conflicts() > 0 {
+        return Err(report);
+    }
+    if settings.policy == Policy::KeepAll {
+        // Nothing was merged, so the entries keep their original order
+        return Ok((raw, report));
+    }
+    return Ok((output, report));
+}
+
+fn report_group(raw: &TrainingDataRaw, group: &[usize], config: &Config, result: Option<String>) -> Group {
+    let colors: Vec<_> = group.iter().map(|i| color(&raw[*i]).to_oklab()).collect();
+    let mut distance: MainType = 0.0;
+    for (i, a) in colors.iter().enumerate() {
+        for b in &colors[i + 1..] {
+            distance = distance.max(((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt());
+        }
+    }
+
+    let first = &raw[group[0]];
+    return Group {
+        key: key(&first.name, config),
+        exact: group.iter().all(|i| raw[*i].name == first.name),
+        distance,
+        conflict: distance > config.duplicates.conflict_distance,
+        entries: group.iter().map(|i| ReportEntry {
+            name: raw[*i].name.clone(),
+            color: raw[*i].color.clone(),
+            source: raw[*i].source.clone(),
+        }).collect(),
+        result,
+    };
+}
+
+fn color(entry: &RawEntry) -> Color {
+    return Color::from_str(&entry.color).unwrap_or_else(|_| panic!("Invalid colour {:?} for {:?}", entry.color, entry.name));
+}
+
+/// `Color::to_hex` rounds down, which would make averages drift
+fn to_hex(color: Color) -> String {
+    let (r, g, b) = color.to_rgb();
+    let channel = |c: MainType| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    return format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::{Config, RawEntry, TrainingDataRaw};
+
+    use super::deduplicate;
+
+    fn data() -> TrainingDataRaw {
+        return vec![
+            RawEntry::new("Ghost White", "#f8f8ff"),
+            RawEntry::new("red", "#ff0000"),
+            RawEntry::new("GhostWhite", "#f8f8ff"),
+            RawEntry::new("blue", "#000000"),
+            RawEntry::new("blue", "#0000ff"),
+            RawEntry::new("green", "#00ff00"),
+        ];
+    }
+
+    fn names(data: &TrainingDataRaw) -> Vec<(&str, &str)> {
+        return data.iter().map(|e| (e.name.as_str(), e.color.as_str())).collect();
+    }
+
+    #[test]
+    fn finds_duplicates() {
+        let (output, report) = deduplicate(data(), &Config::test(r#"{ "duplicates": { "policy": "keep_all" } }"#)).unwrap();
+        assert_eq!(output, data());
+        assert_eq!(report.groups.len(), 2);
+
+        let ghost = &report.groups[0];
+        assert_eq!(ghost.key, "ghostwhite");
+        assert!(!ghost.exact && !ghost.conflict);
+        assert_eq!(ghost.distance, 0.0);
+        assert_eq!(ghost.result, None);
+
+        let blue = &report.groups[1];
+        assert!(blue.exact && blue.conflict);
+        assert!(blue.distance > 0.4);
+        assert_eq!(report.conflicts(), 1);
+    }
+
+    #[test]
+    fn applies_policy() {
+        let (output, _) = deduplicate(data(), &Config::test(r#"{ "duplicates": { "policy": "keep_first" } }"#)).unwrap();
+        assert_eq!(names(&output), vec![("Ghost White", "#f8f8ff"), ("red", "#ff0000"), ("blue", "#000000"), ("green", "#00ff00")]);
+
+        let (output, report) = deduplicate(data(), &Config::test(r#"{ "duplicates": { "policy": "average" } }"#)).unwrap();
+        assert_eq!(names(&output)[..2], [("Ghost White", "#f8f8ff"), ("red", "#ff0000")]);
+        // Halfway between black and blue in OkLab, which isn't halfway in srgb
+        assert_eq!(output[2].name, "blue");
+        assert_ne!(output[2].color, "#000080");
+        assert_eq!(report.groups[1].result.as_deref(), Some(output[2].color.as_str()));
+
+        let report = deduplicate(data(), &Config::test(r#"{ "duplicates": { "policy": "error" } }"#)).unwrap_err();
+        assert_eq!(report.conflicts(), 1);
+        // Duplicates which agree aren't a problem
+        let agreeing: TrainingDataRaw = data().into_iter().filter(|e| e.name != "blue").collect();
+        let (output, _) = deduplicate(agreeing, &Config::test(r#"{ "duplicates": { "policy": "error" } }"#)).unwrap();
+        assert_eq!(output.len(), 3);
+    }
+}
@@ -11,10 +11,13 @@ pub struct GpuDeviceData {
 pub async fn init_gpu() -> GpuDeviceData {
     let adapter = init_adapter().await;
     println!("Using gpu adapter: {:?}", adapter.get_info());
+    return open_device(adapter).await;
+}
 
+pub async fn open_device(adapter: Adapter) -> GpuDeviceData {
     let mut desc = DeviceDescriptor::default();
     let device = adapter.get_info().device_type;
-    if device == DeviceType::Cpu || device == DeviceType::IntegratedGpu {
+    if (device == DeviceType::Cpu || device == DeviceType::IntegratedGpu) && adapter.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS) {
         desc.required_features |= Features::MAPPABLE_PRIMARY_BUFFERS;
     }
     let (device, queue) = adapter.request_device(&desc, None).await.expect("Failed to open GPU");
@@ -29,6 +32,11 @@ pub async fn init_gpu() -> GpuDeviceData {
 }
 
 pub async fn init_adapter() -> Adapter {
+    return request_adapter().await.expect("Couldn't request WebGPU adapter. Please ensure WebGPU is available for your device");
+}
+
+/// Like `init_adapter`, but returns None if there's no adapter available
+pub async fn request_adapter() -> Option<Adapter> {
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
         backends: wgpu::Backends::all(),
         dx12_shader_compiler: Default::default(),
@@ -40,5 +48,5 @@ pub async fn init_adapter() -> Adapter {
         power_preference: wgpu::PowerPreference::default(),
         force_fallback_adapter: false,
         compatible_surface: None,
-    }).await.expect("Couldn't request WebGPU adapter. Please ensure WebGPU is available for your device");
+    }).await;
 }
\ No newline at end of file
@@ -0,0 +1,419 @@
+use std::{fmt::Display, fs, path::Path};
+
+use serde::{de::{MapAccess, Visitor}, Deserialize, Deserializer};
+
+use super::wikitext::Wikitext;
+
+/// A single sample, as read from a training data file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct RawEntry {
+    pub name: String,
+    /// Anything `Color::from_str` understands
+    #[serde(alias = "colour")]
+    pub color: String,
+    /// How much the sample counts compared to the others
+    #[serde(default = "RawEntry::default_weight")]
+    pub weight: f64,
+    /// Where the sample came from, such as the name of the list it was taken from
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The line the sample is on, if the reader keeps track of it. Only used to point at the sample in messages
+    #[serde(skip)]
+    pub line: Option<usize>,
+}
+
+impl RawEntry {
+    pub fn new(name: impl Into<String>, color: impl Into<String>) -> Self {
+        Self { name: name.into(), color: color.into(), weight: Self::default_weight(), source: None, line: None }
+    }
+
+    fn default_weight() -> f64 {
+        1.0
+    }
+}
+
+/// Turns the contents of a training data file into samples
+pub trait DataReader {
+    /// Fails on the first row which can't be used
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String>;
+
+    /// Like `read`, but leaves out the rows which can't be used and returns them, instead of failing.
+    /// Only formats which can always tell where the next row starts do this, the others fail like `read`
+    fn read_skipping(&self, text: &str) -> Result<(Vec<RawEntry>, Vec<Skipped>), String> {
+        Ok((self.read(text)?, Vec::new()))
+    }
+}
+
+/// A row which was left out of the training data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    /// The name of the file. Filled in by `read`
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+/// A single json object mapping names to colours. Entries keep the order of the file, including names which are listed more than once
+pub struct JsonMap;
+
+/// One json object per line, with a `name`, a `color` and optionally a `weight` and `source`
+pub struct JsonLines;
+
+/// Comma or tab separated values. The first row is the header, which is used to find the columns
+pub struct Delimited {
+    pub delimiter: char,
+    /// Whether fields can be quoted, as csv allows
+    pub quoted: bool,
+    pub columns: Columns,
+}
+
+/// The layout of X11's `rgb.txt`: three decimal channels followed by the name, with `!` starting a comment
+pub struct X11;
+
+/// Which header belongs to which field. Headers are compared case-insensitively
+#[derive(Clone, Debug, PartialEq)]
+pub struct Columns {
+    pub name: Vec<String>,
+    pub color: Vec<String>,
+    pub weight: Vec<String>,
+    pub source: Vec<String>,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        Self {
+            name: strings(&["name", "colour name", "color name"]),
+            color: strings(&["color", "colour", "hex", "rgb"]),
+            weight: strings(&["weight"]),
+            source: strings(&["source"]),
+        }
+    }
+}
+
+impl Columns {
+    /// Parses a mapping such as `name=Colour Name,color=Hex`. Fields which aren't mentioned keep their default headers
+    pub fn parse(mapping: &str) -> Result<Self, String> {
+        let mut columns = Self::default();
+        for pair in mapping.split(',').filter(|p| !p.trim().is_empty()) {
+            let (field, header) = pair.split_once('=').ok_or_else(|| format!("Expected field=header, got {pair:?}"))?;
+            let header = vec![header.trim().to_owned()];
+            match field.trim() {
+                "name" => columns.name = header,
+                "color" | "colour" => columns.color = header,
+                "weight" => columns.weight = header,
+                "source" => columns.source = header,
+                other => return Err(format!("Unknown field {other:?}, expected name, color, weight or source")),
+            }
+        }
+        return Ok(columns);
+    }
+}
+
+/// Picks a reader by name (`json`, `jsonl`, `csv`, `tsv`, `x11` or `wikitext`), or by the extension of the file if there's no name.
+/// `rgb.txt` and other `.txt` files are read as X11 lists
+pub fn reader_for(path: &Path, format: Option<&str>, columns: Columns) -> Result<Box<dyn DataReader>, String> {
+    let format = match format {
+        Some(format) => format.to_lowercase(),
+        None => path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase())
+            .ok_or_else(|| format!("Can't tell the format of {path:?}, pass --format"))?,
+    };
+    let reader: Box<dyn DataReader> = match format.as_str() {
+        "json" => Box::new(JsonMap),
+        "jsonl" | "ndjson" => Box::new(JsonLines),
+        "csv" => Box::new(Delimited { delimiter: ',', quoted: true, columns }),
+        "tsv" => Box::new(Delimited { delimiter: '\t', quoted: false, columns }),
+        "x11" | "txt" => Box::new(X11),
+        "wikitext" => Box::new(Wikitext),
+        other => return Err(format!("Unknown training data format {other:?}")),
+    };
+    return Ok(reader);
+}
+
+/// Reads the file with the given reader, panicking with the file name if it can't be read.
+/// Samples without a source get the name of the file.
+/// Also returns the rows which were left out, see `DataReader::read_skipping`
+pub fn read(path: &Path, reader: &dyn DataReader) -> (Vec<RawEntry>, Vec<Skipped>) {
+    let text = fs::read_to_string(path).expect("Can't open training data file");
+    let (mut entries, mut skipped) = reader.read_skipping(&text).unwrap_or_else(|e| panic!("Invalid training data in {path:?}: {e}"));
+    let file_name = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+    for entry in &mut entries {
+        entry.source.get_or_insert_with(|| file_name.clone());
+    }
+    for row in &mut skipped {
+        row.file.clone_from(&file_name);
+    }
+    (entries, skipped)
+}
+
+impl DataReader for JsonMap {
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String> {
+        let pairs: Pairs = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        Ok(pairs.0)
+    }
+}
+
+/// The entries of a json object, in order. Unlike a map this keeps every pair when a name is repeated
+struct Pairs(Vec<RawEntry>);
+
+impl<'de> Deserialize<'de> for Pairs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PairsVisitor;
+
+        impl<'de> Visitor<'de> for PairsVisitor {
+            type Value = Pairs;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an object mapping names to colours")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Pairs, A::Error> {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some((name, color)) = map.next_entry::<String, String>()? {
+                    entries.push(RawEntry::new(name, color));
+                }
+                Ok(Pairs(entries))
+            }
+        }
+
+        deserializer.deserialize_map(PairsVisitor)
+    }
+}
+
+impl DataReader for JsonLines {
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String> {
+        let mut output = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            output.push(serde_json::from_str(line).map_err(|e| format!("line {}: {e}", i + 1))?);
+        }
+        return Ok(output);
+    }
+}
+
+impl DataReader for Delimited {
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String> {
+        let mut rows = self.rows(text)?.into_iter().filter(|(_, row)| row.iter().any(|f| !f.trim().is_empty()));
+        let (_, header) = rows.next().ok_or("The file is empty")?;
+
+        let find = |aliases: &[String]| header.iter().position(|h| aliases.iter().any(|a| a.eq_ignore_ascii_case(h.trim())));
+        let name = find(&self.columns.name).ok_or_else(|| format!("No name column in header {header:?}"))?;
+        let color = find(&self.columns.color).ok_or_else(|| format!("No colour column in header {header:?}"))?;
+        let weight = find(&self.columns.weight);
+        let source = find(&self.columns.source);
+
+        let mut output = Vec::new();
+        for (line, row) in rows {
+            let field = |i: usize| row.get(i).map(|f| f.trim()).ok_or_else(|| format!("line {line}: expected {} fields, got {}", header.len(), row.len()));
+            let mut entry = RawEntry::new(field(name)?, field(color)?);
+            if let Some(weight) = weight.map(field).transpose()?.filter(|w| !w.is_empty()) {
+                entry.weight = weight.parse().map_err(|_| format!("line {line}: invalid weight {weight:?}"))?;
+            }
+            entry.source = source.map(field).transpose()?.filter(|s| !s.is_empty()).map(str::to_owned);
+            output.push(entry);
+        }
+        return Ok(output);
+    }
+}
+
+impl Delimited {
+    /// Splits the text into rows of fields, along with the line each row starts on.
+    /// Quoted fields may contain the delimiter, newlines and doubled quotes
+    fn rows(&self, text: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut line = 1;
+        let mut row_start = 1;
+
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => in_quotes = false,
+                    _ => field.push(c),
+                }
+                if c == '\n' {
+                    line += 1;
+                }
+                continue;
+            }
+            match c {
+                '"' if self.quoted && field.trim().is_empty() => {
+                    field.clear();
+                    in_quotes = true;
+                }
+                '\n' => {
+                    row.push(std::mem::take(&mut field).trim_end_matches('\r').to_owned());
+                    rows.push((row_start, std::mem::take(&mut row)));
+                    line += 1;
+                    row_start = line;
+                }
+                c if c == self.delimiter => row.push(std::mem::take(&mut field)),
+                _ => field.push(c),
+            }
+        }
+        if in_quotes {
+            return Err(format!("line {row_start}: unterminated quote"));
+        }
+        if !field.is_empty() || !row.is_empty() {
+            row.push(field);
+            rows.push((row_start, row));
+        }
+        return Ok(rows);
+    }
+}
+
+impl DataReader for X11 {
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String> {
+        let mut output = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('!') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let mut channel = || -> Result<u8, String> {
+                let part = parts.next().ok_or_else(|| format!("line {}: expected three channels and a name", i + 1))?;
+                return part.parse().map_err(|_| format!("line {}: invalid channel {part:?}", i + 1));
+            };
+            let (r, g, b) = (channel()?, channel()?, channel()?);
+            // Names can contain spaces, so the name is everything after the channels
+            let name = skip_fields(line, 3);
+            if name.is_empty() {
+                return Err(format!("line {}: missing name", i + 1));
+            }
+            output.push(RawEntry::new(name, format!("#{r:02x}{g:02x}{b:02x}")));
+        }
+        return Ok(output);
+    }
+}
+
+/// The rest of the string after `count` whitespace separated fields
+fn skip_fields(str: &str, count: usize) -> &str {
+    let mut rest = str.trim();
+    for _ in 0..count {
+        rest = rest.trim_start_matches(|c: char| !c.is_whitespace()).trim_start();
+    }
+    return rest;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use super::{read, reader_for, Columns, DataReader, Delimited, JsonLines, JsonMap, RawEntry, Wikitext, X11};
+
+    fn csv(columns: Columns) -> Delimited {
+        Delimited { delimiter: ',', quoted: true, columns }
+    }
+
+    #[test]
+    fn reads_json_map() {
+        let entries = JsonMap.read(r##"{ "red": "#ff0000", "blue": "#0000ff", "red": "#fe0000" }"##).unwrap();
+        // In file order, keeping both reds so the duplicates policy can deal with them
+        assert_eq!(entries, vec![RawEntry::new("red", "#ff0000"), RawEntry::new("blue", "#0000ff"), RawEntry::new("red", "#fe0000")]);
+        assert!(JsonMap.read(r##"["red", "#ff0000"]"##).is_err());
+    }
+
+    #[test]
+    fn reads_json_lines() {
+        let text = r##"{ "name": "red", "color": "#ff0000" }
+
+{ "name": "Blue", "colour": "#0000ff", "weight": 2.5, "source": "xkcd" }
+"##;
+        let entries = JsonLines.read(text).unwrap();
+        assert_eq!(entries[0], RawEntry::new("red", "#ff0000"));
+        assert_eq!(entries[1], RawEntry { weight: 2.5, source: Some("xkcd".to_owned()), ..RawEntry::new("Blue", "#0000ff") });
+
+        let error = JsonLines.read("{ \"name\": \"red\", \"color\": \"#ff0000\" }\n{ \"name\": \"blue\" }").unwrap_err();
+        assert!(error.starts_with("line 2"), "{error}");
+    }
+
+    #[test]
+    fn reads_csv() {
+        let text = "Hex,Colour Name,Notes\r\n#ff0000,red,\n\"#00ff00\",\"green, but \"\"lime\"\"\",\"spans\ntwo lines\"\n\n#0000ff,blue,x\n";
+        let entries = csv(Columns::default()).read(text).unwrap();
+        assert_eq!(entries, vec![
+            RawEntry::new("red", "#ff0000"),
+            RawEntry::new("green, but \"lime\"", "#00ff00"),
+            RawEntry::new("blue", "#0000ff"),
+        ]);
+
+        let text = "title,value,weight,source\nred,#ff0000,0.5,wiki\nblue,#0000ff,,\n";
+        let entries = csv(Columns::parse("name=Title, color=value").unwrap()).read(text).unwrap();
+        assert_eq!(entries[0], RawEntry { weight: 0.5, source: Some("wiki".to_owned()), ..RawEntry::new("red", "#ff0000") });
+        assert_eq!(entries[1], RawEntry::new("blue", "#0000ff"));
+
+        assert!(csv(Columns::default()).read("title,hex\nred,#ff0000").unwrap_err().contains("No name column"));
+        assert!(csv(Columns::default()).read("name,hex\nred").unwrap_err().starts_with("line 2"));
+        assert!(csv(Columns::default()).read("name,hex\n\"red,#ff0000").unwrap_err().contains("unterminated"));
+        assert!(Columns::parse("hue=x").is_err());
+    }
+
+    #[test]
+    fn reads_tsv() {
+        let reader = Delimited { delimiter: '\t', quoted: false, columns: Columns::default() };
+        let entries = reader.read("name\tcolor\n\"quoted\" red\t#ff0000\n").unwrap();
+        assert_eq!(entries, vec![RawEntry::new("\"quoted\" red", "#ff0000")]);
+    }
+
+    #[test]
+    fn reads_x11() {
+        let text = "! $Xorg: rgb.txt,v 1.3 2000/08/17 19:54:00 cpqbld Exp $\n255 250 250\t\tsnow\n248 248 255\t\tghost white\n  0   0 128\t\tNavyBlue\n";
+        let entries = X11.read(text).unwrap();
+        assert_eq!(entries, vec![
+            RawEntry::new("snow", "#fffafa"),
+            RawEntry::new("ghost white", "#f8f8ff"),
+            RawEntry::new("NavyBlue", "#000080"),
+        ]);
+        assert!(X11.read("255 250\n").is_err());
+        assert!(X11.read("256 0 0 red\n").is_err());
+        assert!(X11.read("255 0 0\n").is_err());
+    }
+
+    #[test]
+    fn picks_reader() {
+        let read = |path: &str, format: Option<&str>, text: &str| reader_for(Path::new(path), format, Columns::default()).unwrap().read(text).unwrap();
+        assert_eq!(read("data.json", None, r##"{ "red": "#ff0000" }"##), vec![RawEntry::new("red", "#ff0000")]);
+        assert_eq!(read("data.JSONL", None, r##"{ "name": "red", "color": "#ff0000" }"##), vec![RawEntry::new("red", "#ff0000")]);
+        assert_eq!(read("rgb.txt", None, "255 0 0 red"), vec![RawEntry::new("red", "#ff0000")]);
+        assert_eq!(read("A-F.wikitext", None, "{{Colort/Color|hex=FF0000|name=[[Red]]}}"), vec![RawEntry { line: Some(1), ..RawEntry::new("Red", "#ff0000") }]);
+        assert_eq!(read("colours", Some("tsv"), "name\tcolor\nred\t#ff0000"), vec![RawEntry::new("red", "#ff0000")]);
+        // The flag wins over the extension
+        assert_eq!(read("colours.json", Some("csv"), "name,color\nred,#ff0000"), vec![RawEntry::new("red", "#ff0000")]);
+
+        assert!(reader_for(Path::new("colours"), None, Columns::default()).is_err());
+        assert!(reader_for(Path::new("colours.xml"), None, Columns::default()).is_err());
+    }
+
+    #[test]
+    fn adds_file_to_source() {
+        let dir = std::env::temp_dir().join(format!("formats-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("colours.wikitext"), "{{Colort/Color|hex=FF0000|name=Red}}\n{{Colort/Color|name=Blue}}").unwrap();
+        fs::write(dir.join("colours.jsonl"), r##"{ "name": "red", "color": "#ff0000" }"##).unwrap();
+
+        let (wikitext, skipped) = read(&dir.join("colours.wikitext"), &Wikitext);
+        let (jsonl, _) = read(&dir.join("colours.jsonl"), &JsonLines);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(wikitext[0].source.as_deref(), Some("colours.wikitext"));
+        assert_eq!(wikitext[0].line, Some(1));
+        assert_eq!(skipped[0].to_string(), "colours.wikitext:2: no colour for \"Blue\"");
+        assert_eq!(jsonl[0].source.as_deref(), Some("colours.jsonl"));
+    }
+}
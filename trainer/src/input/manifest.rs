@@ -0,0 +1,81 @@
+use std::{fs::File, path::Path};
+
+use serde::Deserialize;
+
+use super::{formats::{self, Columns, Skipped}, TrainingDataRaw};
+
+/// Lists the files the training data is made of. Paths are relative to the manifest
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub sources: Vec<Source>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Source {
+    pub path: String,
+    /// See `formats::reader_for`. Taken from the extension if it's not set
+    #[serde(default)]
+    pub format: Option<String>,
+    /// For csv and tsv files, see `Columns::parse`
+    #[serde(default)]
+    pub columns: Option<String>,
+    /// Multiplies the weight of every sample in this source.
+    /// 2 makes each sample count twice during training, 0 leaves the source out of training entirely
+    #[serde(default = "Source::default_weight")]
+    pub weight: f64,
+    /// Attached to every sample, so the evaluation can be broken down per source
+    pub tag: String,
+}
+
+impl Source {
+    fn default_weight() -> f64 {
+        1.0
+    }
+}
+
+/// Reads every source in the manifest. Samples are tagged with their source, replacing any source they had in the file.
+/// Also returns the rows which were left out of every file, see `formats::read`
+pub fn load(path: &Path) -> (TrainingDataRaw, Vec<Skipped>) {
+    let manifest: Manifest = serde_json::from_reader(File::open(path).expect("Can't open manifest")).unwrap();
+    let mut output = Vec::new();
+    let mut skipped = Vec::new();
+    for source in manifest.sources {
+        let file = path.parent().unwrap().join(&source.path);
+        let columns = source.columns.as_deref().map_or_else(|| Ok(Columns::default()), Columns::parse).unwrap_or_else(|e| panic!("{e}"));
+        let reader = formats::reader_for(&file, source.format.as_deref(), columns).unwrap_or_else(|e| panic!("{e}"));
+        let (entries, file_skipped) = formats::read(&file, reader.as_ref());
+        for mut entry in entries {
+            entry.weight *= source.weight;
+            entry.source = Some(source.tag.clone());
+            output.push(entry);
+        }
+        skipped.extend(file_skipped);
+    }
+    (output, skipped)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::load;
+
+    #[test]
+    fn combines_sources() {
+        let dir = std::env::temp_dir().join(format!("manifest-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lists")).unwrap();
+        fs::write(dir.join("lists/wiki.json"), r##"{ "red": "#ff0000", "blue": "#0000ff" }"##).unwrap();
+        fs::write(dir.join("palette"), "Title,Hex,weight\nlime,#00ff00,3\n").unwrap();
+        fs::write(dir.join("manifest.json"), r#"{ "sources": [
+            { "path": "lists/wiki.json", "tag": "wikipedia" },
+            { "path": "palette", "format": "csv", "columns": "name=Title", "weight": 0.5, "tag": "community" }
+        ] }"#).unwrap();
+
+        let (entries, skipped) = load(&dir.join("manifest.json"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let summary: Vec<_> = entries.iter().map(|e| (e.name.as_str(), e.weight, e.source.as_deref().unwrap())).collect();
+        assert_eq!(summary, vec![("red", 1.0, "wikipedia"), ("blue", 1.0, "wikipedia"), ("lime", 1.5, "community")]);
+        assert!(skipped.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use std::{fs::File, path::Path};
+
+use serde::Deserialize;
+
+use crate::{augment::Augmentation, duplicates::Duplicates, layer::Size, split::Split, normalize::Normalizer, string::{Alphabet, HashedFeatures, Layout}};
+
+pub mod formats;
+pub mod manifest;
+pub mod wikitext;
+
+pub use formats::RawEntry;
+
+pub type TrainingDataRaw = Vec<RawEntry>;
+
+#[derive(Deserialize)]
+pub struct Config {
+    input_length: Size,
+    pub percentage_training: f64,
+    layers: Vec<Size>,
+    /// How names are cleaned up before they're encoded
+    #[serde(default)]
+    pub normalize: Normalizer,
+    /// How characters are mapped to input nodes
+    #[serde(default)]
+    pub alphabet: Alphabet,
+    /// Where each character goes in the input
+    #[serde(default)]
+    pub layout: Layout,
+    /// Extra inputs that don't depend on the position of the characters
+    #[serde(default)]
+    pub hashed_features: Option<HashedFeatures>,
+    /// Extra variants of the training names
+    #[serde(default)]
+    pub augmentation: Augmentation,
+    /// What to do with names that occur more than once
+    #[serde(default)]
+    pub duplicates: Duplicates,
+    /// How the names are divided between the training and checking sets
+    #[serde(default)]
+    pub split: Split,
+}
+
+#[derive(Clone, Copy)]
+pub struct LayerConfig {
+    /// Size of the preceeding layer
+    pub previous_size: Size,
+    pub size: Size,
+    /// Set iff this layer takes the (one-hot encoded) network input.
+    /// Contains the maximum amount of input nodes which can be active at once
+    pub active_inputs: Option<Size>,
+}
+
+impl Config {
+    /// Reads the config and any files it refers to. Paths in the config are relative to the config file
+    pub fn load(path: &Path) -> Self {
+        let mut config: Config = serde_json::from_reader(File::open(path).expect("Can't open config file")).unwrap();
+        if let Some(modifiers) = &config.augmentation.modifiers {
+            let modifiers = path.parent().unwrap().join(modifiers);
+            config.augmentation.modifier_rules = serde_json::from_reader(File::open(modifiers).expect("Can't open modifiers file")).unwrap();
+        }
+        return config;
+    }
+
+    pub fn layers(&self) -> Vec<LayerConfig> {
+        let mut output = Vec::with_capacity(self.layers.len());
+
+        for (i, layer_size) in self.layers.iter().copied().enumerate() {
+            output.push(LayerConfig {
+                previous_size: if i == 0 { self.input_length() } else { self.layers[i-1] },
+                size: layer_size,
+                active_inputs: if i == 0 { Some(self.active_inputs()) } else { None },
+            });
+        }
+
+        return output;
+    }
+
+    /// The number of layers in the neural network.
+    /// Does not count the input layer, but does counts the output layer.
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn input_length(&self) -> Size {
+        let hashed = self.hashed_features.as_ref().map_or(0, |h| h.buckets);
+        return crate::string::get_input_size(self.input_length, &self.alphabet) + hashed;
+    }
+
+    pub fn input_length_max_chars(&self) -> Size {
+        return self.input_length;
+    }
+
+    /// The maximum amount of input nodes which can be active at once.
+    /// Each character activates a single node, plus any hashed features.
+    pub fn active_inputs(&self) -> Size {
+        let hashed = self.hashed_features.as_ref().map_or(0, |h| h.active_inputs());
+        return self.input_length + hashed;
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// A small config for tests: 16 characters, 90% training and a single layer of 3.
+    /// `fields` is a json object whose fields are added to, or replace, those
+    pub fn test(fields: &str) -> Self {
+        let mut config = serde_json::json!({ "input_length": 16, "percentage_training": 0.9, "layers": [3] });
+        let fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(fields).unwrap();
+        config.as_object_mut().unwrap().extend(fields);
+        serde_json::from_value(config).unwrap()
+    }
+}
+
+/// Each layer decides how its own parameters are stored
+pub type JsonNetworkParameters = Vec<serde_json::Value>;
\ No newline at end of file
@@ -0,0 +1,298 @@
+use super::{formats::{DataReader, Skipped}, RawEntry};
+
+/// Reads the rows of Wikipedia's colour lists, such as
+/// `{{Colort/Color|hex=F0F8FF|r=240|g=248|b=255|name=[[Alice blue]]}}`, from the page source.
+/// Rows may use named or positional parameters, and the name may contain links, nested templates and markup.
+/// `read_skipping` leaves out the rows which can't be used
+pub struct Wikitext;
+
+/// Templates which describe a single colour
+const ROW_TEMPLATES: [&str; 1] = ["colort/color"];
+
+impl DataReader for Wikitext {
+    fn read(&self, text: &str) -> Result<Vec<RawEntry>, String> {
+        let (entries, skipped) = parse(text);
+        match skipped.first() {
+            Some(row) => Err(format!("line {}: {}", row.line, row.message)),
+            None => Ok(entries),
+        }
+    }
+
+    fn read_skipping(&self, text: &str) -> Result<(Vec<RawEntry>, Vec<Skipped>), String> {
+        Ok(parse(text))
+    }
+}
+
+/// Finds every row in the page. The entries and skipped rows get the line their template starts on
+pub fn parse(text: &str) -> (Vec<RawEntry>, Vec<Skipped>) {
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    let mut lines = LineCounter { page: text, position: 0, line: 1 };
+    parse_templates(text, 0, &mut lines, &mut entries, &mut skipped);
+    (entries, skipped)
+}
+
+/// Keeps track of the line number while going through the page. Positions have to be passed in order,
+/// so every part of the page is only counted once
+struct LineCounter<'a> {
+    page: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl LineCounter<'_> {
+    fn line_at(&mut self, position: usize) -> usize {
+        self.line += self.page[self.position..position].bytes().filter(|b| *b == b'\n').count();
+        self.position = position;
+        self.line
+    }
+}
+
+/// Finds the rows in `text`, which starts at `offset` in the page. Rows inside of other templates are found as well
+fn parse_templates(text: &str, offset: usize, lines: &mut LineCounter, entries: &mut Vec<RawEntry>, skipped: &mut Vec<Skipped>) {
+    let mut i = 0;
+    while let Some(start) = text[i..].find("{{").map(|s| s + i) {
+        let line = lines.line_at(offset + start);
+        let skip = |message| Skipped { file: String::new(), line, message };
+        let Some(end) = matching_end(text, start, "{{", "}}") else {
+            skipped.push(skip("unterminated template".to_owned()));
+            return;
+        };
+        let inner = &text[start + 2..end - 2];
+        let params = split_params(inner);
+        if is_row(params[0]) {
+            match parse_row(&params[1..]) {
+                Ok(entry) => entries.push(RawEntry { line: Some(line), ..entry }),
+                Err(message) => skipped.push(skip(message)),
+            }
+        } else {
+            parse_templates(inner, offset + start + 2, lines, entries, skipped);
+        }
+        i = end;
+    }
+}
+
+fn is_row(template: &str) -> bool {
+    let template = template.trim().replace('_', " ").to_lowercase();
+    ROW_TEMPLATES.contains(&template.as_str())
+}
+
+fn parse_row(params: &[&str]) -> Result<RawEntry, String> {
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+    for param in params {
+        match split_named(param) {
+            Some((key, value)) => named.push((key, value)),
+            None => positional.push(*param),
+        }
+    }
+    let get = |key: &str, position: usize| {
+        named.iter().find(|(k, _)| *k == key).map(|(_, v)| *v).or(positional.get(position).copied())
+    };
+
+    let name = plain_text(get("name", 1).ok_or("no name")?);
+    if name.is_empty() {
+        return Err("empty name".to_owned());
+    }
+    let color = match get("hex", 0).map(plain_text).filter(|h| !h.is_empty()) {
+        Some(hex) => parse_hex(&hex).ok_or_else(|| format!("invalid hex value {hex:?} for {name:?}"))?,
+        None => {
+            let channel = |key: &str| get(key, usize::MAX).and_then(|c| plain_text(c).parse::<u8>().ok());
+            match (channel("r"), channel("g"), channel("b")) {
+                (Some(r), Some(g), Some(b)) => format!("#{r:02x}{g:02x}{b:02x}"),
+                _ => return Err(format!("no colour for {name:?}")),
+            }
+        }
+    };
+    Ok(RawEntry::new(name, color))
+}
+
+fn parse_hex(hex: &str) -> Option<String> {
+    let hex = hex.trim_start_matches('#');
+    if !(hex.len() == 6 || hex.len() == 3) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("#{}", hex.to_lowercase()))
+}
+
+/// The index just past the `close` that matches the `open` at `start`
+fn matching_end(text: &str, start: usize, open: &str, close: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < text.len() {
+        if text[i..].starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else if text[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    None
+}
+
+/// Splits the inside of a template or link on the `|`s which aren't inside another template or link
+fn split_params(inner: &str) -> Vec<&str> {
+    let mut params = Vec::new();
+    let mut depth = 0i32;
+    let mut start = 0;
+    let mut i = 0;
+    while i < inner.len() {
+        let rest = &inner[i..];
+        if rest.starts_with("{{") || rest.starts_with("[[") {
+            depth += 1;
+            i += 2;
+        } else if rest.starts_with("}}") || rest.starts_with("]]") {
+            depth -= 1;
+            i += 2;
+        } else {
+            if depth == 0 && rest.starts_with('|') {
+                params.push(&inner[start..i]);
+                start = i + 1;
+            }
+            i += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    params.push(&inner[start..]);
+    params
+}
+
+/// Splits `key=value`. The key can't contain any markup, otherwise the `=` belongs to the value
+fn split_named(param: &str) -> Option<(&str, &str)> {
+    let (key, value) = param.split_once('=')?;
+    if key.contains(['{', '[', '<']) {
+        return None;
+    }
+    Some((key.trim(), value))
+}
+
+/// Renders wikitext as the text a reader would see.
+/// Links become their label, unknown templates become their last positional parameter,
+/// non-breaking spaces become spaces, and comments, references, html tags and bold or italic quotes are removed
+pub fn plain_text(wikitext: &str) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < wikitext.len() {
+        let rest = &wikitext[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map_or(rest.len(), |e| e + 3);
+        } else if rest.starts_with("<ref") {
+            let tag_end = rest.find('>').map_or(rest.len(), |e| e + 1);
+            if rest[..tag_end].ends_with("/>") {
+                i += tag_end;
+            } else {
+                i += rest.find("</ref>").map_or(rest.len(), |e| e + "</ref>".len());
+            }
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') {
+            // Other tags such as <small> and <br/> are dropped, keeping their contents
+            i += rest.find('>').map_or(rest.len(), |e| e + 1);
+            output.push(' ');
+        } else if rest.starts_with("[[") {
+            // Unterminated links and templates run to the end of the text
+            let end = matching_end(wikitext, i, "[[", "]]");
+            let inner = &wikitext[i + 2..end.map_or(wikitext.len(), |e| e - 2)];
+            let end = end.unwrap_or(wikitext.len());
+            output.push_str(&plain_text(split_params(inner).last().unwrap()));
+            i = end;
+        } else if rest.starts_with("{{") {
+            // Unterminated links and templates run to the end of the text
+            let end = matching_end(wikitext, i, "{{", "}}");
+            let inner = &wikitext[i + 2..end.map_or(wikitext.len(), |e| e - 2)];
+            let end = end.unwrap_or(wikitext.len());
+            let params = split_params(inner);
+            if let Some(last) = params[1..].iter().rev().find(|p| split_named(p).is_none()) {
+                output.push_str(&plain_text(last));
+            }
+            i = end;
+        } else if rest.starts_with("[http") || rest.starts_with("[//") {
+            // External links show their label, if they have one
+            let end = rest.find(']').unwrap_or(rest.len());
+            output.push_str(rest[..end].split_once(' ').map_or("", |l| l.1));
+            i += (end + 1).min(rest.len());
+        } else if rest.starts_with("&nbsp;") {
+            output.push(' ');
+            i += "&nbsp;".len();
+        } else if rest.starts_with("''") {
+            i += rest.len() - rest.trim_start_matches('\'').len();
+        } else {
+            let c = rest.chars().next().unwrap();
+            output.push(c);
+            i += c.len_utf8();
+        }
+    }
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::{formats::DataReader, RawEntry};
+
+    use super::{parse, plain_text, split_params, Wikitext};
+
+    const FIXTURE: &str = include_str!("../../fixtures/wikitext/colors.wikitext");
+
+    #[test]
+    fn renders_plain_text() {
+        assert_eq!(plain_text("[[Alice blue]]"), "Alice blue");
+        assert_eq!(plain_text("[[Aero (color)|Aero]]"), "Aero");
+        assert_eq!(plain_text("'''Bold''' ''italic''"), "Bold italic");
+        assert_eq!(plain_text("Amber<ref name=\"a\">{{cite web|url=x}}</ref> (SAE)<ref name=\"b\"/>"), "Amber (SAE)");
+        assert_eq!(plain_text("{{nowrap|Baby blue}}<!-- not baby pink -->"), "Baby blue");
+        assert_eq!(plain_text("{{lang|fr|[[Bleu de France|Bleu&nbsp;de France]]}}"), "Bleu de France");
+        assert_eq!(plain_text("Blue<br/>(Crayola)"), "Blue (Crayola)");
+        assert_eq!(plain_text("[https://example.com Cerulean] frost"), "Cerulean frost");
+        assert_eq!(plain_text("[[Red→"), "Red→");
+        assert_eq!(plain_text("{{nowrap|Red→"), "Red→");
+    }
+
+    #[test]
+    fn splits_params() {
+        assert_eq!(split_params("Colort/Color|hex=fff|name=[[a|b]]|{{x|y}}"), vec!["Colort/Color", "hex=fff", "name=[[a|b]]", "{{x|y}}"]);
+    }
+
+    #[test]
+    fn parses_fixture() {
+        let (entries, skipped) = parse(FIXTURE);
+        let names: Vec<_> = entries.iter().map(|e| (e.name.as_str(), e.color.as_str())).collect();
+        assert_eq!(names, vec![
+            ("Absolute Zero", "#0048ba"),
+            ("Aero", "#7cb9e8"),
+            ("Alice blue", "#f0f8ff"),
+            ("Amber (SAE/ECE)", "#ff7e00"),
+            ("Baby blue", "#89cff0"),
+            ("Bleu de France", "#318ce7"),
+            ("Blue (Crayola)", "#1f75fe"),
+            ("Celadon", "#ace1af"),
+            ("Cerulean frost", "#6d9bc3"),
+            ("Dark cyan", "#008b8b"),
+            ("Red→", "#ff0000"),
+        ]);
+        assert!(entries.iter().all(|e| *e == RawEntry { line: e.line, ..RawEntry::new(e.name.clone(), e.color.clone()) }));
+        let lines: Vec<_> = entries.iter().map(|e| e.line.unwrap()).collect();
+        assert_eq!(lines, vec![6, 7, 8, 9, 13, 15, 16, 19, 20, 21, 25]);
+
+        let lines: Vec<_> = skipped.iter().map(|s| s.line).collect();
+        assert_eq!(lines, vec![22, 23, 24], "{skipped:?}");
+        assert!(skipped[0].message.contains("invalid hex"));
+        assert!(skipped[1].message.contains("no name"));
+        assert!(skipped[2].message.contains("no colour"));
+
+        // Without skipping, the first bad row is an error
+        assert!(Wikitext.read(FIXTURE).unwrap_err().starts_with("line 22: invalid hex"));
+        assert_eq!(Wikitext.read_skipping(FIXTURE).unwrap(), (entries, skipped));
+    }
+
+    #[test]
+    fn counts_lines() {
+        let (entries, skipped) = parse("{{Colort/Color|hex=000000|name=Black}}\n{{Div col|\n\n{{Colort/Color|hex=808080|name=Grey}}}}\n{{Colort/Color|hex=ffffff|name=White");
+        let lines: Vec<_> = entries.iter().map(|e| e.line.unwrap()).collect();
+        assert_eq!(lines, vec![1, 4]);
+        assert_eq!(skipped[0].line, 5);
+        assert_eq!(skipped[0].message, "unterminated template");
+    }
+}
@@ -0,0 +1,494 @@
+use futures::{future::LocalBoxFuture, try_join, FutureExt};
+use serde::{Deserialize, Serialize};
+use wgpu::{BindGroup, Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePass, Device, Features};
+
+use crate::{gpu::GpuDeviceData, input::LayerConfig, misc::{bind_group, size_of, SliceExtension}, shaders::{self, ApplyGradientsShaderPipeline, BackpropApplyBiasShaderPipeline, BackpropApplyWeightShaderPipeline, TiledShaderPipeline}};
+
+use super::{BackwardsBuffers, ForwardsBuffers, Gradients, Layer, LayerInput, LayerPass, MainType, ReadableBuf};
+
+/// A fully connected layer, each node is connected to every node in the previous layer.
+pub struct DenseLayer {
+    pub(super) config: LayerConfig,
+    pub(super) weights: Buffer,
+    pub(super) biases: Buffer,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonDenseLayer {
+    pub weights: Vec<MainType>,
+    pub biases: Vec<MainType>,
+}
+
+impl DenseLayer {
+    pub fn load(config: LayerConfig, device: &Device, json: &serde_json::Value) -> Self {
+        let json = JsonDenseLayer::deserialize(json).expect("Invalid parameters for dense layer");
+        Self::create_inner(config, device, |weights, biases| {
+            weights.copy_from_slice(bytemuck::cast_slice(&json.weights));
+            biases.copy_from_slice(bytemuck::cast_slice(&json.biases));
+        })
+    }
+
+    pub fn create(config: LayerConfig, device: &Device) -> Self {
+        Self::create_inner(config, device, |weights, biases| {
+            biases.fill(0);
+            bytemuck::cast_slice_mut(weights).iter_mut().for_each(
+                |c: &mut MainType| *c = rand::random::<MainType>() * (2.0 / config.size as MainType)
+            );
+        })
+    }
+
+    fn create_inner<F>(config: LayerConfig, device: &Device, init: F) -> Self
+            where F: FnOnce(&mut [u8], &mut [u8]) {
+        let mut usage = BufferUsages::STORAGE;
+
+        if device.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS) {
+            usage |= BufferUsages::MAP_READ;
+        } else {
+            usage |= BufferUsages::COPY_SRC;
+        }
+
+        let weights = device.create_buffer(&BufferDescriptor {
+            label: Some("nn layer weights"),
+            size: config.previous_size * config.size * size_of::<MainType>(),
+            usage,
+            mapped_at_creation: true
+        });
+        let biases = device.create_buffer(&BufferDescriptor {
+            label: Some("nn layer biases"),
+            size: config.size * size_of::<MainType>(),
+            usage,
+            mapped_at_creation: true
+        });
+
+        init(&mut weights.slice(..).get_mapped_range_mut(), &mut biases.slice(..).get_mapped_range_mut());
+
+        weights.unmap();
+        biases.unmap();
+
+        Self {
+            config,
+            weights,
+            biases,
+        }
+    }
+
+    pub(super) fn apply_biases(&self, gpu: &GpuDeviceData, deriv_z: &Buffer, gradients: &Gradients, invocations: usize) -> (BackpropApplyBiasShaderPipeline, BindGroup) {
+        let pipeline = shaders::compile_apply_backprop_biases(gpu, self.config.size, invocations);
+        let bind_group = gpu.device.create_bind_group(&bind_group! {
+            &pipeline.get_layout(),
+            0 => deriv_z,
+            1 => &gradients.biases,
+            2 => &pipeline.partial_sums,
+        });
+        (pipeline, bind_group)
+    }
+
+    pub(super) fn create_gradients_inner(&self, gpu: &GpuDeviceData) -> Gradients {
+        // Buffers are zeroed when they're created
+        let create = |buffer: &Buffer| gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("nn layer gradients"),
+            size: buffer.size(),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false
+        });
+        Gradients {
+            weights: create(&self.weights),
+            biases: create(&self.biases),
+        }
+    }
+
+    pub(super) fn apply_gradients_inner(&self, gpu: &GpuDeviceData, gradients: &Gradients, invocations: usize) -> Box<dyn LayerPass> {
+        let apply = |gradients: &Buffer, parameters: &Buffer, size| {
+            let pipeline = shaders::compile_apply_gradients(gpu, size, invocations);
+            let bind_group = gpu.device.create_bind_group(&bind_group! {
+                &gpu.shader_components.apply_gradients.0,
+                0 => gradients,
+                1 => parameters,
+            });
+            (pipeline, bind_group)
+        };
+
+        Box::new(DenseApplyGradients {
+            weights: apply(&gradients.weights, &self.weights, self.config.previous_size * self.config.size),
+            biases: apply(&gradients.biases, &self.biases, self.config.size),
+        })
+    }
+
+    pub(super) async fn to_json_inner(&self, gpu: &GpuDeviceData) -> JsonDenseLayer {
+        let weights;
+        let biases;
+
+        if gpu.device.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS) {
+            weights = ReadableBuf::Primary(&self.weights);
+            biases = ReadableBuf::Primary(&self.biases);
+        } else {
+            let weight_staging = gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("weight staging"),
+                size: self.weights.size(),
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            });
+            let bias_staging = gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("bias staging"),
+                size: self.biases.size(),
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            });
+
+            let mut command = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+            command.copy_buffer_to_buffer(&self.weights, 0, &weight_staging, 0, self.weights.size());
+            command.copy_buffer_to_buffer(&self.biases, 0, &bias_staging, 0, self.biases.size());
+            gpu.queue.submit([command.finish()]);
+            gpu.device.poll(wgpu::MaintainBase::Wait);
+
+            weights = ReadableBuf::Staging(weight_staging);
+            biases = ReadableBuf::Staging(bias_staging);
+        }
+
+        let weights_slice = weights.slice(..);
+        let biases_slice = biases.slice(..);
+
+        try_join!(
+            weights_slice.map_buffer(&gpu.device, wgpu::MapMode::Read),
+            biases_slice.map_buffer(&gpu.device, wgpu::MapMode::Read),
+        ).unwrap();
+
+        let weights_mapped = weights_slice.get_mapped_range();
+        let weights_copy = Vec::from(bytemuck::cast_slice(&weights_mapped));
+        let biases_mapped = biases_slice.get_mapped_range();
+        let biases_copy = Vec::from(bytemuck::cast_slice(&biases_mapped));
+
+        drop(weights_mapped);
+        drop(biases_mapped);
+
+        weights.unmap();
+        biases.unmap();
+
+        JsonDenseLayer {
+            weights: weights_copy,
+            biases: biases_copy,
+        }
+    }
+}
+
+impl Layer for DenseLayer {
+    fn forwards(&self, gpu: &GpuDeviceData, buffers: ForwardsBuffers, invocations: usize) -> Box<dyn LayerPass> {
+        let LayerInput::Activations(input_a) = buffers.input else {
+            panic!("Dense layers can't take the network input directly");
+        };
+
+        let pipeline = shaders::compile_compute_forwards(gpu, self.config.previous_size, self.config.size, invocations);
+        let bind_group = gpu.device.create_bind_group(&bind_group! {
+            &gpu.shader_components.compute_forwards.0,
+            0 => &self.weights,
+            1 => &self.biases,
+            2 => input_a,
+            3 => buffers.output_z,
+            4 => buffers.output_a,
+        });
+
+        Box::new(DenseForwards {
+            pipeline,
+            bind_group,
+        })
+    }
+
+    fn backwards(&self, gpu: &GpuDeviceData, buffers: BackwardsBuffers, invocations: usize) -> Box<dyn LayerPass> {
+        let LayerInput::Activations(input_a) = buffers.input else {
+            panic!("Dense layers can't take the network input directly");
+        };
+
+        // The derivatives of the previous layer are computed using the weights of this layer,
+        // so this needs to be done before the weights are changed
+        let backpropagation = Option::zip(buffers.input_z, buffers.input_deriv_z).map(|(input_z, input_deriv_z)| {
+            let pipeline = shaders::compile_backpropagation(gpu, self.config.previous_size, self.config.size, invocations);
+            let bind_group = gpu.device.create_bind_group(&bind_group! {
+                &gpu.shader_components.backpropagation.0,
+                0 => &self.weights,
+                1 => input_z,
+                2 => buffers.deriv_z,
+                3 => input_deriv_z,
+            });
+            (pipeline, bind_group)
+        });
+
+        // Adds the derivatives of both the weights and the biases to the gradients
+        let apply_weights = shaders::compile_apply_backprop_weights(gpu, self.config.previous_size, self.config.size, invocations);
+        let apply_weights_bind_group = gpu.device.create_bind_group(&bind_group! {
+            &apply_weights.get_layout(),
+            0 => input_a,
+            1 => buffers.deriv_z,
+            2 => &buffers.gradients.weights,
+            3 => &buffers.gradients.biases,
+            4 => &apply_weights.partial_sums,
+        });
+
+        Box::new(DenseBackwards {
+            backpropagation,
+            apply_weights: (apply_weights, apply_weights_bind_group),
+        })
+    }
+
+    fn create_gradients(&self, gpu: &GpuDeviceData) -> Gradients {
+        self.create_gradients_inner(gpu)
+    }
+
+    fn apply_gradients(&self, gpu: &GpuDeviceData, gradients: &Gradients, invocations: usize) -> Box<dyn LayerPass> {
+        self.apply_gradients_inner(gpu, gradients, invocations)
+    }
+
+    fn to_json<'a>(&'a self, gpu: &'a GpuDeviceData) -> LocalBoxFuture<'a, serde_json::Value> {
+        async move {
+            serde_json::to_value(self.to_json_inner(gpu).await).unwrap()
+        }.boxed_local()
+    }
+}
+
+struct DenseForwards {
+    pipeline: TiledShaderPipeline,
+    bind_group: BindGroup,
+}
+
+impl LayerPass for DenseForwards {
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>) {
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        self.pipeline.setup_pass(pass);
+    }
+}
+
+struct DenseBackwards {
+    /// Will be None for the first layer, the input layer doesn't need derivatives
+    backpropagation: Option<(TiledShaderPipeline, BindGroup)>,
+    apply_weights: (BackpropApplyWeightShaderPipeline, BindGroup),
+}
+
+impl LayerPass for DenseBackwards {
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>) {
+        if let Some((pipeline, bind_group)) = &self.backpropagation {
+            pass.set_bind_group(0, bind_group, &[]);
+            pipeline.setup_pass(pass);
+        }
+
+        pass.set_bind_group(0, &self.apply_weights.1, &[]);
+        self.apply_weights.0.setup_pass(pass);
+    }
+}
+
+struct DenseApplyGradients {
+    weights: (ApplyGradientsShaderPipeline, BindGroup),
+    biases: (ApplyGradientsShaderPipeline, BindGroup),
+}
+
+impl LayerPass for DenseApplyGradients {
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>) {
+        pass.set_bind_group(0, &self.weights.1, &[]);
+        self.weights.0.setup_pass(pass);
+        pass.set_bind_group(0, &self.biases.1, &[]);
+        self.biases.0.setup_pass(pass);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use wgpu::{util::{BufferInitDescriptor, DeviceExt}, Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor};
+
+    use crate::{gpu::{open_device, request_adapter, GpuDeviceData}, input::LayerConfig, layer::{BackwardsBuffers, ForwardsBuffers, Layer, LayerInput, MainType}, misc::SliceExtension};
+
+    use super::{DenseLayer, JsonDenseLayer};
+
+    // Sizes which aren't a multiple of the tile size, so the edges of the tiles get tested as well
+    const INVOCATIONS: usize = 37;
+    const INPUT_SIZE: usize = 45;
+    const LAYER_SIZE: usize = 19;
+    const NEXT_LAYER_SIZE: usize = 11;
+
+    fn activation(x: MainType) -> MainType {
+        if x >= 0.0 { x } else { 0.01 * x }
+    }
+
+    fn d_activation(x: MainType) -> MainType {
+        if x >= 0.0 { 1.0 } else { 0.01 }
+    }
+
+    fn random_values(rng: &mut ChaCha8Rng, amount: usize) -> Vec<MainType> {
+        (0..amount).map(|_| rng.gen_range(-1.0..1.0)).collect()
+    }
+
+    fn random_layer(rng: &mut ChaCha8Rng, gpu: &GpuDeviceData, previous_size: usize, size: usize) -> (DenseLayer, JsonDenseLayer) {
+        let json = JsonDenseLayer {
+            weights: random_values(rng, previous_size * size),
+            biases: random_values(rng, size),
+        };
+        let config = LayerConfig { previous_size: previous_size as u64, size: size as u64, active_inputs: None };
+        let layer = DenseLayer::load(config, &gpu.device, &serde_json::to_value(&json).unwrap());
+        (layer, json)
+    }
+
+    fn buffer(gpu: &GpuDeviceData, contents: &[MainType]) -> Buffer {
+        gpu.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(contents),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        })
+    }
+
+    async fn read(gpu: &GpuDeviceData, buffer: &Buffer) -> Vec<MainType> {
+        let staging = gpu.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        commands.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        gpu.queue.submit([commands.finish()]);
+
+        staging.slice(..).map_buffer(&gpu.device, wgpu::MapMode::Read).await.unwrap();
+        let output = Vec::from(bytemuck::cast_slice(&staging.slice(..).get_mapped_range()));
+        staging.unmap();
+        return output;
+    }
+
+    fn assert_close(gpu: &[MainType], cpu: &[MainType]) {
+        assert_eq!(gpu.len(), cpu.len());
+        for (i, (gpu, cpu)) in Iterator::zip(gpu.iter(), cpu.iter()).enumerate() {
+            assert!((gpu - cpu).abs() <= 1e-4 * cpu.abs().max(1.0), "Mismatch at {i}: gpu gave {gpu}, expected {cpu}");
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_cpu_reference() {
+        let Some(adapter) = request_adapter().await else {
+            eprintln!("No gpu adapter available, skipping test");
+            return;
+        };
+        let gpu = open_device(adapter).await;
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        let (layer, layer_json) = random_layer(&mut rng, &gpu, INPUT_SIZE, LAYER_SIZE);
+        let (next_layer, next_layer_json) = random_layer(&mut rng, &gpu, LAYER_SIZE, NEXT_LAYER_SIZE);
+        let input_a = random_values(&mut rng, INVOCATIONS * INPUT_SIZE);
+        let next_deriv_z = random_values(&mut rng, INVOCATIONS * NEXT_LAYER_SIZE);
+
+        // Compute everything on the cpu
+        let mut expected_z = vec![0.0; INVOCATIONS * LAYER_SIZE];
+        for invocation in 0..INVOCATIONS {
+            for node in 0..LAYER_SIZE {
+                let mut z = layer_json.biases[node];
+                for input in 0..INPUT_SIZE {
+                    z += input_a[input + invocation * INPUT_SIZE] * layer_json.weights[input + node * INPUT_SIZE];
+                }
+                expected_z[node + invocation * LAYER_SIZE] = z;
+            }
+        }
+        let expected_a: Vec<_> = expected_z.iter().map(|z| activation(*z)).collect();
+        let mut expected_deriv_z = vec![0.0; INVOCATIONS * LAYER_SIZE];
+        for invocation in 0..INVOCATIONS {
+            for node in 0..LAYER_SIZE {
+                let mut deriv_a = 0.0;
+                for next_node in 0..NEXT_LAYER_SIZE {
+                    deriv_a += next_layer_json.weights[node + next_node * LAYER_SIZE] * next_deriv_z[next_node + invocation * NEXT_LAYER_SIZE];
+                }
+                let i = node + invocation * LAYER_SIZE;
+                expected_deriv_z[i] = d_activation(expected_z[i]) * deriv_a;
+            }
+        }
+
+        // And on the gpu
+        let input_a_buf = buffer(&gpu, &input_a);
+        let z_buf = buffer(&gpu, &vec![0.0; INVOCATIONS * LAYER_SIZE]);
+        let a_buf = buffer(&gpu, &vec![0.0; INVOCATIONS * LAYER_SIZE]);
+        let deriv_z_buf = buffer(&gpu, &vec![0.0; INVOCATIONS * LAYER_SIZE]);
+        let next_deriv_z_buf = buffer(&gpu, &next_deriv_z);
+        let gradients = next_layer.create_gradients_inner(&gpu);
+
+        let forwards = layer.forwards(&gpu, ForwardsBuffers {
+            input: LayerInput::Activations(&input_a_buf),
+            output_z: &z_buf,
+            output_a: &a_buf,
+        }, INVOCATIONS);
+        let backwards = next_layer.backwards(&gpu, BackwardsBuffers {
+            input: LayerInput::Activations(&a_buf),
+            input_z: Some(&z_buf),
+            input_deriv_z: Some(&deriv_z_buf),
+            deriv_z: &next_deriv_z_buf,
+            gradients: &gradients,
+        }, INVOCATIONS);
+
+        let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        let mut pass = commands.begin_compute_pass(&Default::default());
+        forwards.record(&mut pass);
+        backwards.record(&mut pass);
+        drop(pass);
+        gpu.queue.submit([commands.finish()]);
+
+        assert_close(&read(&gpu, &z_buf).await, &expected_z);
+        assert_close(&read(&gpu, &a_buf).await, &expected_a);
+        assert_close(&read(&gpu, &deriv_z_buf).await, &expected_deriv_z);
+    }
+
+    #[tokio::test]
+    async fn applies_averaged_gradients() {
+        let Some(adapter) = request_adapter().await else {
+            eprintln!("No gpu adapter available, skipping test");
+            return;
+        };
+        let gpu = open_device(adapter).await;
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+
+        // Enough invocations for the sums to be split into multiple chunks
+        const INVOCATIONS: usize = 10_000;
+        // Should match lib.wgsl
+        const LEARNING_RATE: f64 = 0.01;
+
+        let (layer, layer_json) = random_layer(&mut rng, &gpu, INPUT_SIZE, LAYER_SIZE);
+        // Using only positive values, so the sums don't cancel out and there's actually something to check
+        let input_a: Vec<MainType> = (0..INVOCATIONS * INPUT_SIZE).map(|_| rng.gen_range(0.0..1.0)).collect();
+        let deriv_z: Vec<MainType> = (0..INVOCATIONS * LAYER_SIZE).map(|_| rng.gen_range(0.0..1.0)).collect();
+
+        // The cpu sums in f64, so it's a good bit more precise than the gpu
+        let mut expected_weights_change = vec![0.0; INPUT_SIZE * LAYER_SIZE];
+        let mut expected_biases_change = vec![0.0; LAYER_SIZE];
+        for invocation in 0..INVOCATIONS {
+            for node in 0..LAYER_SIZE {
+                let deriv_z = deriv_z[node + invocation * LAYER_SIZE] as f64;
+                expected_biases_change[node] -= LEARNING_RATE * deriv_z / INVOCATIONS as f64;
+                for input in 0..INPUT_SIZE {
+                    expected_weights_change[input + node * INPUT_SIZE] -= LEARNING_RATE * input_a[input + invocation * INPUT_SIZE] as f64 * deriv_z / INVOCATIONS as f64;
+                }
+            }
+        }
+
+        let input_a_buf = buffer(&gpu, &input_a);
+        let deriv_z_buf = buffer(&gpu, &deriv_z);
+        let gradients = layer.create_gradients_inner(&gpu);
+        let backwards = layer.backwards(&gpu, BackwardsBuffers {
+            input: LayerInput::Activations(&input_a_buf),
+            input_z: None,
+            input_deriv_z: None,
+            deriv_z: &deriv_z_buf,
+            gradients: &gradients,
+        }, INVOCATIONS);
+        let apply_gradients = layer.apply_gradients_inner(&gpu, &gradients, INVOCATIONS);
+
+        let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: None });
+        let mut pass = commands.begin_compute_pass(&Default::default());
+        backwards.record(&mut pass);
+        apply_gradients.record(&mut pass);
+        drop(pass);
+        gpu.queue.submit([commands.finish()]);
+
+        let result = layer.to_json_inner(&gpu).await;
+        let check = |before: &[MainType], after: &[MainType], expected_change: &[f64]| {
+            for i in 0..before.len() {
+                let change = (after[i] - before[i]) as f64;
+                // Most of the error comes from subtracting the parameters, not from the sum
+                assert!((change - expected_change[i]).abs() <= 1e-6, "Mismatch at {i}: gpu changed it by {change}, expected {}", expected_change[i]);
+            }
+        };
+        check(&layer_json.weights, &result.weights, &expected_weights_change);
+        check(&layer_json.biases, &result.biases, &expected_biases_change);
+    }
+}
@@ -0,0 +1,250 @@
+use std::ops::Deref;
+
+use futures::{future::LocalBoxFuture, stream, StreamExt};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePass, Device, Features};
+
+use crate::{color::Color, gpu::GpuDeviceData, input::{Config, JsonNetworkParameters, LayerConfig}, misc::size_of, string::NO_INPUT, training_data::{GpuInputData, Source}};
+
+use self::{dense::DenseLayer, one_hot::OneHotLayer};
+
+pub mod dense;
+pub mod one_hot;
+
+// Should match lib.wgsl
+pub type MainType = f32;
+
+/// Type for layer sizes
+pub type Size = u64;
+
+pub type Network = Vec<Box<dyn Layer>>;
+
+/// The input of the network. The input is one-hot encoded (see string.rs), so instead of storing
+/// every activation we only store which input nodes are active.
+pub struct InputBuffers {
+    /// For each invocation, the indices of the active input nodes. Padded at the end with `NO_INPUT`
+    /// type: array<array<u32, active_inputs>, invocations>
+    pub active: Buffer,
+    /// The same information as `active`, but grouped by input node. Used to compute the derivatives of the weights.
+    /// For each input node, this is the start of its range in `column_invocations`. Has an extra entry for the end of the last range.
+    /// type: array<u32, input_size + 1>
+    pub column_offsets: Buffer,
+    /// The invocations in which each input node is active
+    /// type: array<u32>
+    pub column_invocations: Buffer,
+}
+
+/// The values (z, a or derivZ) of each layer. Does not include the input layer, see `InputBuffers` for that
+pub struct LayerValues {
+    pub buffers: Vec<Buffer>,
+    /// In bytes
+    size_of_last_layer: Size,
+}
+
+/// A single layer of the neural network. The layer owns its parameters and
+/// knows how to compute itself on the gpu, so the rest of the trainer doesn't
+/// need to care about what kind of layer it is.
+pub trait Layer {
+    /// Creates the gpu work which computes the z and a values of this layer from
+    /// the activations of the previous layer.
+    fn forwards(&self, gpu: &GpuDeviceData, buffers: ForwardsBuffers, invocations: usize) -> Box<dyn LayerPass>;
+
+    /// Creates the gpu work which backpropagates through this layer. By the time this runs, `buffers.deriv_z`
+    /// will contain the derivatives of this layer. The pass should compute the derivatives of the previous
+    /// layer (if requested) and add the derivatives of its own parameters to `buffers.gradients`.
+    fn backwards(&self, gpu: &GpuDeviceData, buffers: BackwardsBuffers, invocations: usize) -> Box<dyn LayerPass>;
+
+    /// Creates zeroed buffers to accumulate the derivatives of the parameters in
+    fn create_gradients(&self, gpu: &GpuDeviceData) -> Gradients;
+
+    /// Creates the gpu work which applies the accumulated gradients to the parameters, averaged
+    /// over `invocations`. The gradients are zeroed again afterwards.
+    fn apply_gradients(&self, gpu: &GpuDeviceData, gradients: &Gradients, invocations: usize) -> Box<dyn LayerPass>;
+
+    fn to_json<'a>(&'a self, gpu: &'a GpuDeviceData) -> LocalBoxFuture<'a, serde_json::Value>;
+}
+
+/// A piece of gpu work with all of its pipelines and bind groups ready to go.
+pub trait LayerPass {
+    /// Records the work into a compute pass. Everything is recorded into the same pass, wgpu will
+    /// make sure each dispatch sees the results of the ones before it
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>);
+}
+
+/// What a layer gets as its input
+#[derive(Clone, Copy)]
+pub enum LayerInput<'a> {
+    /// The activations of the previous layer
+    Activations(&'a Buffer),
+    /// The input of the whole network
+    OneHot(&'a InputBuffers),
+}
+
+pub struct ForwardsBuffers<'a> {
+    pub input: LayerInput<'a>,
+    pub output_z: &'a Buffer,
+    pub output_a: &'a Buffer,
+}
+
+pub struct BackwardsBuffers<'a> {
+    pub input: LayerInput<'a>,
+    /// The z-values and derivatives of the previous layer. These are None if the previous layer
+    /// is the input layer, as there's no need to backpropagate into the input.
+    pub input_z: Option<&'a Buffer>,
+    pub input_deriv_z: Option<&'a Buffer>,
+    /// The derivatives of the z values of this layer
+    pub deriv_z: &'a Buffer,
+    /// The derivatives of every chunk of the data are added up here, and only applied once every chunk is done
+    pub gradients: &'a Gradients,
+}
+
+/// The summed derivatives of the parameters of a layer. These have the same layout as the parameters themselves.
+pub struct Gradients {
+    pub weights: Buffer,
+    pub biases: Buffer,
+}
+
+/// Creates a new, randomly initialized, layer
+pub fn create(config: LayerConfig, device: &Device) -> Box<dyn Layer> {
+    if config.active_inputs.is_some() {
+        Box::new(OneHotLayer::create(config, device))
+    } else {
+        Box::new(DenseLayer::create(config, device))
+    }
+}
+
+pub fn load(config: LayerConfig, device: &Device, json: &serde_json::Value) -> Box<dyn Layer> {
+    if config.active_inputs.is_some() {
+        Box::new(OneHotLayer::load(config, device, json))
+    } else {
+        Box::new(DenseLayer::load(config, device, json))
+    }
+}
+
+pub async fn to_json(network: Network, gpu: &GpuDeviceData) -> JsonNetworkParameters {
+    stream::iter(network).then(|l| async move { l.to_json(gpu).await }).collect().await
+}
+
+pub fn from_json(parameters: &JsonNetworkParameters, config: &Config, gpu: &GpuDeviceData) -> Network {
+    let mut output = Network::default();
+    for (i, layer) in config.layers().into_iter().enumerate() {
+        output.push(load(layer, &gpu.device, &parameters[i]));
+    }
+    return output;
+}
+
+impl LayerValues {
+    pub fn create(gpu: &GpuDeviceData, config: &Config, invocations: usize) -> Self {
+        let mut buffers = Vec::new();
+
+        for (i, layer) in config.layers().iter().enumerate() {
+            let mut usage = BufferUsages::STORAGE;
+            if i == config.layers().len()-1 {
+                if gpu.device.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS) {
+                    usage |= BufferUsages::MAP_READ;
+                } else {
+                    usage |= BufferUsages::COPY_SRC;
+                }
+            }
+            buffers.push(gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("nn layer values"),
+                size: layer.size * invocations as u64 * size_of::<MainType>(),
+                usage,
+                mapped_at_creation: false
+            }));
+        }
+
+        Self {
+            buffers,
+            size_of_last_layer: config.layers().last().unwrap().size * invocations as u64 * size_of::<MainType>()
+        }
+    }
+
+    pub fn read_output(&self, gpu: &GpuDeviceData, encoder: &mut CommandEncoder) -> ReadableBuf<'_> {
+        let output_buf = self.buffers.last().unwrap();
+        if gpu.device.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS) {
+            return ReadableBuf::Primary(output_buf);
+        } else {
+            let staging = gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("nn layer staging"),
+                size: self.size_of_last_layer,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false
+            });
+            encoder.copy_buffer_to_buffer(output_buf, 0, &staging, 0, self.size_of_last_layer);
+            return ReadableBuf::Staging(staging);
+        }
+    }
+}
+
+impl InputBuffers {
+    pub fn create(gpu: &GpuDeviceData, config: &Config, data: &[(GpuInputData, Color, Source)]) -> Self {
+        let active_inputs = config.active_inputs() as usize;
+        let active = gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("nn layer inputs"),
+            size: (active_inputs * data.len()) as u64 * size_of::<u32>(),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: true
+        });
+        Iterator::zip(
+            data.iter().map(|data| &data.0),
+            bytemuck::cast_slice_mut::<_, u32>(&mut active.slice(..).get_mapped_range_mut()).chunks_exact_mut(active_inputs)
+        ).for_each(|(input_data, gpu_value)| {
+            gpu_value.copy_from_slice(input_data);
+        });
+        active.unmap();
+
+        // Group the invocations by which input nodes they activate.
+        // This is done by counting how often each node is active, which then tells us where each node's range starts
+        let mut column_offsets = vec![0u32; config.input_length() as usize + 1];
+        for (input_data, _, _) in data {
+            for node in input_data.iter().take_while(|n| **n != NO_INPUT) {
+                column_offsets[*node as usize + 1] += 1;
+            }
+        }
+        for i in 1..column_offsets.len() {
+            column_offsets[i] += column_offsets[i - 1];
+        }
+        let mut column_invocations = vec![0u32; *column_offsets.last().unwrap() as usize];
+        let mut column_fill = column_offsets.clone();
+        for (invocation, (input_data, _, _)) in data.iter().enumerate() {
+            for node in input_data.iter().take_while(|n| **n != NO_INPUT) {
+                column_invocations[column_fill[*node as usize] as usize] = invocation as u32;
+                column_fill[*node as usize] += 1;
+            }
+        }
+        // Empty buffers can't be bound
+        if column_invocations.is_empty() {
+            column_invocations.push(0);
+        }
+
+        Self {
+            active,
+            column_offsets: gpu.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("nn input column offsets"),
+                contents: bytemuck::cast_slice(&column_offsets),
+                usage: BufferUsages::STORAGE,
+            }),
+            column_invocations: gpu.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("nn input column invocations"),
+                contents: bytemuck::cast_slice(&column_invocations),
+                usage: BufferUsages::STORAGE,
+            }),
+        }
+    }
+}
+
+pub enum ReadableBuf<'a> {
+    Primary(&'a Buffer),
+    Staging(Buffer)
+}
+
+impl Deref for ReadableBuf<'_> {
+    type Target = Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ReadableBuf::Primary(x) => x,
+            ReadableBuf::Staging(x) => x,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,111 @@
+use futures::{future::LocalBoxFuture, FutureExt};
+use wgpu::{BindGroup, ComputePass, Device};
+
+use crate::{gpu::GpuDeviceData, input::LayerConfig, misc::bind_group, shaders::{self, ApplyOneHotWeightShaderPipeline, BackpropApplyBiasShaderPipeline, StandardShaderPipeline}};
+
+use super::{dense::DenseLayer, BackwardsBuffers, ForwardsBuffers, Gradients, Layer, LayerInput, LayerPass};
+
+/// A fully connected layer which takes the one-hot encoded network input.
+/// Its parameters are the same as a dense layer, but since at most a handful of
+/// input nodes are active, it only has to look at those.
+pub struct OneHotLayer {
+    dense: DenseLayer,
+}
+
+impl OneHotLayer {
+    pub fn load(config: LayerConfig, device: &Device, json: &serde_json::Value) -> Self {
+        Self { dense: DenseLayer::load(config, device, json) }
+    }
+
+    pub fn create(config: LayerConfig, device: &Device) -> Self {
+        Self { dense: DenseLayer::create(config, device) }
+    }
+}
+
+impl Layer for OneHotLayer {
+    fn forwards(&self, gpu: &GpuDeviceData, buffers: ForwardsBuffers, invocations: usize) -> Box<dyn LayerPass> {
+        let LayerInput::OneHot(input) = buffers.input else {
+            panic!("One-hot layers can only take the network input");
+        };
+        let config = &self.dense.config;
+
+        let pipeline = shaders::compile_compute_forwards_one_hot(gpu, config.previous_size, config.size, config.active_inputs.unwrap(), invocations);
+        let bind_group = gpu.device.create_bind_group(&bind_group! {
+            &gpu.shader_components.compute_forwards_one_hot.0,
+            0 => &self.dense.weights,
+            1 => &self.dense.biases,
+            2 => &input.active,
+            3 => buffers.output_z,
+            4 => buffers.output_a,
+        });
+
+        Box::new(OneHotForwards {
+            pipeline,
+            bind_group,
+        })
+    }
+
+    fn backwards(&self, gpu: &GpuDeviceData, buffers: BackwardsBuffers, invocations: usize) -> Box<dyn LayerPass> {
+        let LayerInput::OneHot(input) = buffers.input else {
+            panic!("One-hot layers can only take the network input");
+        };
+        let config = &self.dense.config;
+
+        let apply_biases = self.dense.apply_biases(gpu, buffers.deriv_z, buffers.gradients, invocations);
+
+        let apply_weights = shaders::compile_apply_backprop_weights_one_hot(gpu, config.previous_size, config.size);
+        let apply_weights_bind_group = gpu.device.create_bind_group(&bind_group! {
+            &gpu.shader_components.apply_backprop_weights_one_hot.0,
+            0 => &input.column_offsets,
+            1 => &input.column_invocations,
+            2 => buffers.deriv_z,
+            3 => &buffers.gradients.weights,
+        });
+
+        Box::new(OneHotBackwards {
+            apply_biases,
+            apply_weights: (apply_weights, apply_weights_bind_group),
+        })
+    }
+
+    fn create_gradients(&self, gpu: &GpuDeviceData) -> Gradients {
+        self.dense.create_gradients_inner(gpu)
+    }
+
+    fn apply_gradients(&self, gpu: &GpuDeviceData, gradients: &Gradients, invocations: usize) -> Box<dyn LayerPass> {
+        self.dense.apply_gradients_inner(gpu, gradients, invocations)
+    }
+
+    fn to_json<'a>(&'a self, gpu: &'a GpuDeviceData) -> LocalBoxFuture<'a, serde_json::Value> {
+        async move {
+            serde_json::to_value(self.dense.to_json_inner(gpu).await).unwrap()
+        }.boxed_local()
+    }
+}
+
+struct OneHotForwards {
+    pipeline: StandardShaderPipeline,
+    bind_group: BindGroup,
+}
+
+impl LayerPass for OneHotForwards {
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>) {
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        self.pipeline.setup_pass(pass);
+    }
+}
+
+/// There's no backpropagation step here, the network input doesn't need derivatives
+struct OneHotBackwards {
+    apply_biases: (BackpropApplyBiasShaderPipeline, BindGroup),
+    apply_weights: (ApplyOneHotWeightShaderPipeline, BindGroup),
+}
+
+impl LayerPass for OneHotBackwards {
+    fn record<'a>(&'a self, pass: &mut ComputePass<'a>) {
+        pass.set_bind_group(0, &self.apply_biases.1, &[]);
+        self.apply_biases.0.setup_pass(pass);
+        pass.set_bind_group(0, &self.apply_weights.1, &[]);
+        self.apply_weights.0.setup_pass(pass);
+    }
+}
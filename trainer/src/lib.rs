@@ -12,4 +12,14 @@ pub mod layer;
 pub mod gpu;
 #[allow(dead_code)]
 pub mod color;
-pub mod string;
\ No newline at end of file
+pub mod string;
+pub mod normalize;
+pub mod tokenizer;
+pub mod cpu;
+pub mod conformance;
+pub mod augment;
+pub mod preflight;
+pub mod summary;
+pub mod duplicates;
+pub mod split;
+pub mod stats;
@@ -1,9 +1,13 @@
-use std::{env, fs::File, path::PathBuf};
+use std::{collections::BTreeMap, env, ffi::OsString, fs::File, path::{Path, PathBuf}};
 
-use gpu::init_gpu;
-use input::{Config, TrainingDataRaw};
-use neural_network::train_nn;
-use training_data::process_data;
+use augment::Synthetic;
+use gpu::{init_gpu, GpuDeviceData};
+use input::{formats::{self, Columns, Skipped}, manifest, Config, TrainingDataRaw};
+use neural_network::{benchmark_training, train_nn, ITERATIONS_PER_SUBMISSION};
+use stats::DatasetStats;
+use summary::Summary;
+use tokenizer::Tokenizer;
+use training_data::{check_colors, process_data};
 
 mod input;
 mod training_data;
@@ -15,12 +19,63 @@ mod gpu;
 #[allow(dead_code)]
 mod color;
 mod string;
+mod normalize;
+mod tokenizer;
+mod cpu;
+mod conformance;
+mod augment;
+mod duplicates;
+mod split;
+mod stats;
+mod preflight;
+mod summary;
 
 #[tokio::main]
 async fn main() {
-    let args: Vec<_> = env::args_os().collect();
+    let mut args: Vec<_> = env::args_os().collect();
+    let format = DataOptions {
+        format: take_flag(&mut args, "--format").map(|f| f.to_str().expect("Invalid format").to_owned()),
+        columns: take_flag(&mut args, "--columns").map_or_else(Columns::default, |c| Columns::parse(c.to_str().expect("Invalid column mapping")).unwrap_or_else(|e| panic!("{e}"))),
+        duplicates_report: take_flag(&mut args, "--duplicates-report").map(PathBuf::from),
+        strict: take_switch(&mut args, "--strict"),
+    };
+    let split_report = take_flag(&mut args, "--split-report").map(PathBuf::from);
+    if args.len() == 4 && args[1] == "--benchmark" {
+        benchmark(PathBuf::from(&args[2]), PathBuf::from(&args[3]), &format).await;
+        return;
+    }
+    if args.len() == 3 && args[1] == "--summary" {
+        summary(PathBuf::from(&args[2]));
+        return;
+    }
+    if args.len() == 6 && args[1] == "--train-tokenizer" {
+        let vocab_size = args[4].to_str().and_then(|s| s.parse().ok()).expect("The vocabulary size should be a number");
+        train_tokenizer(PathBuf::from(&args[2]), PathBuf::from(&args[3]), vocab_size, PathBuf::from(&args[5]), &format);
+        return;
+    }
+    if (args.len() == 4 || args.len() == 5) && args[1] == "--dataset-stats" {
+        let percentile = args.get(4).map_or(95.0, |p| p.to_str().and_then(|s| s.parse().ok()).expect("The percentile should be a number"));
+        dataset_stats(PathBuf::from(&args[2]), PathBuf::from(&args[3]), percentile, &format);
+        return;
+    }
+    if args.len() == 6 && args[1] == "--fixtures" {
+        fixtures(PathBuf::from(&args[2]), PathBuf::from(&args[3]), PathBuf::from(&args[4]), PathBuf::from(&args[5]));
+        return;
+    }
     if args.len() != 4 {
         println!("Usage: {:?} <training_data> <nn_config> <output_file>", args[0]);
+        println!("       {:?} --benchmark <training_data> <nn_config>", args[0]);
+        println!("       {:?} --summary <nn_config>", args[0]);
+        println!("       {:?} --train-tokenizer <training_data> <nn_config> <vocab_size> <output_file>", args[0]);
+        println!("       {:?} --dataset-stats <training_data> <nn_config> [percentile]", args[0]);
+        println!("       {:?} --fixtures <nn_config> <network_parameters> <names> <output_file>", args[0]);
+        println!("Training data is read as json, jsonl, csv, tsv, x11 (rgb.txt) or wikitext depending on the file extension.");
+        println!("A manifest (*.manifest.json) combines several files, each with their own weight and tag.");
+        println!("Options: --format <json|jsonl|csv|tsv|x11|wikitext|manifest> to override the extension");
+        println!("         --columns name=<header>,color=<header>,weight=<header>,source=<header> to pick the csv/tsv columns");
+        println!("         --duplicates-report <file> to write every duplicate name that was found, and what was done with it");
+        println!("         --split-report <file> to write which set each name went in");
+        println!("         --strict to refuse to train if any row or colour can't be read, instead of leaving those entries out");
         return;
     }
 
@@ -28,20 +83,219 @@ async fn main() {
     let config_file = PathBuf::from(&args[2]);
     let output_file = PathBuf::from(&args[3]);
 
-    let data: TrainingDataRaw = serde_json::from_reader(File::open(training_data_file).expect("Can't open training data file")).unwrap();
-    let config: Config = serde_json::from_reader(File::open(config_file).expect("Can't open training data file")).unwrap();
+    let config = Config::load(&config_file);
+    let data = load_training_data(&training_data_file, &format, &config);
 
     let (data, truncated_data) = process_data(data, &config);
+    if let Some(split_report) = split_report {
+        serde_json::to_writer_pretty(File::create(split_report).expect("Couldn't open split report file"), &data.assignment).unwrap();
+    }
 
     println!("Starting trainig process!");
-    println!("Training set contains {} entries", data.training.len());
-    println!("Check/verify set contains {} entries", data.checking.len());
+    println!("Training set contains {} entries, {} of which are synthetic", data.training.len(), data.synthetic.len());
+    for kind in [Synthetic::Typo, Synthetic::Modifier] {
+        let entries: Vec<_> = data.synthetic.iter().filter(|s| s.kind == kind).collect();
+        if !entries.is_empty() {
+            println!("  {} {kind:?} variants, such as", entries.len());
+            for entry in entries.iter().step_by(entries.len().div_ceil(5)) {
+                println!("    [synthetic] {:?} {}", entry.name, entry.color.to_hex());
+            }
+        }
+    }
+    println!("Check/verify set contains {} entries ({:?} split)", data.checking.len(), config.split);
+    let mut sources = BTreeMap::<&str, (usize, usize)>::new();
+    data.training.iter().for_each(|e| sources.entry(&e.2).or_default().0 += 1);
+    data.checking.iter().for_each(|e| sources.entry(&e.2).or_default().1 += 1);
+    if sources.len() > 1 {
+        for (source, (training, checking)) in &sources {
+            println!("  {source}: {training} training (after weighting), {checking} checking");
+        }
+    }
     println!("Total: {} entries", data.training.len() + data.checking.len());
-    println!("{} entries were truncated due to configured input size", truncated_data);
+    println!("{} entries were truncated due to configured input size", truncated_data.len());
+    for name in &truncated_data {
+        println!("  {name:?}");
+    }
 
     let gpu = init_gpu().await;
-    let parameters = train_nn(&gpu, data, config).await;
+    check_fits(&gpu, &config, data.training.len(), data.checking.len());
+    let network = train_nn(&gpu, data, config).await;
 
-    let json = layer::to_json(parameters, &gpu).await;
+    let json = layer::to_json(network, &gpu).await;
     serde_json::to_writer(File::create(output_file).expect("Couldn't open output file"), &json).unwrap();
 }
+
+
+/// Measures the training throughput on the given data, without saving anything
+async fn benchmark(training_data_file: PathBuf, config_file: PathBuf, format: &DataOptions) {
+    let config = Config::load(&config_file);
+    let data = load_training_data(&training_data_file, format, &config);
+
+    let (data, _) = process_data(data, &config);
+    let gpu = init_gpu().await;
+    check_fits(&gpu, &config, data.training.len(), 0);
+
+    let iterations = 500;
+    println!("Benchmarking {iterations} training iterations on {} entries", data.training.len());
+    let (single, batched) = benchmark_training(&gpu, &data.training, config, iterations).await;
+    println!("{single:.1} iterations per second when submitting every iteration on its own");
+    println!("{batched:.1} iterations per second when submitting {ITERATIONS_PER_SUBMISSION} iterations at once");
+}
+
+/// Prints how large the network described by the config is
+fn summary(config_file: PathBuf) {
+    let config = Config::load(&config_file);
+    print!("{}", Summary::new(&config));
+}
+
+/// Learns a byte-pair-encoding vocabulary from the names in the training data
+fn train_tokenizer(training_data_file: PathBuf, config_file: PathBuf, vocab_size: usize, output_file: PathBuf, format: &DataOptions) {
+    let (data, skipped) = read_training_data(&training_data_file, format);
+    if !skipped.is_empty() {
+        println!("Leaving out {} rows that can't be read", skipped.len());
+    }
+    let config = Config::load(&config_file);
+
+    let names: Vec<_> = data.iter().map(|entry| normalize::prepare(&entry.name, &config)).collect();
+    let tokenizer = Tokenizer::train(names.iter().map(|n| n.as_str()), vocab_size);
+    let tokens: usize = names.iter().map(|n| tokenizer.encode(n).len()).sum();
+    let chars: usize = names.iter().map(|n| n.len()).sum();
+    println!("Learned {} tokens", tokenizer.vocab_size());
+    println!("Names are {:.2} tokens long on average, down from {:.2} bytes", tokens as f64 / names.len() as f64, chars as f64 / names.len() as f64);
+    for name in names.iter().take(5) {
+        let pieces: Vec<_> = tokenizer.encode(name).into_iter().map(|token| tokenizer.decode(&[token])).collect();
+        println!("  {name:?} is split into {pieces:?}");
+    }
+
+    serde_json::to_writer(File::create(output_file).expect("Couldn't open output file"), &tokenizer).unwrap();
+}
+
+/// Prints what the names and colours in the training data look like, and suggests an input_length
+/// that fits `percentile` percent of the names
+fn dataset_stats(training_data_file: PathBuf, config_file: PathBuf, percentile: f64, format: &DataOptions) {
+    let config = Config::load(&config_file);
+    let (data, skipped) = read_training_data(&training_data_file, format);
+    if !skipped.is_empty() {
+        println!("Leaving out {} rows that can't be read", skipped.len());
+    }
+    let (data, invalid) = check_colors(data);
+    if !invalid.is_empty() {
+        println!("Leaving out {} entries with a colour that can't be parsed", invalid.len());
+    }
+    print!("{}", DatasetStats::new(&data, &config, percentile));
+}
+
+/// Writes the input, activations and output of the network for each name in the `names` json array,
+/// which other implementations can be checked against
+fn fixtures(config_file: PathBuf, parameters_file: PathBuf, names_file: PathBuf, output_file: PathBuf) {
+    let config = serde_json::from_reader(File::open(config_file).expect("Can't open config file")).unwrap();
+    let parameters = serde_json::from_reader(File::open(parameters_file).expect("Can't open network parameters file")).unwrap();
+    let names = serde_json::from_reader(File::open(names_file).expect("Can't open names file")).unwrap();
+
+    let fixtures = conformance::create(config, parameters, names);
+    println!("Wrote {} cases", fixtures.cases.len());
+    serde_json::to_writer(File::create(output_file).expect("Couldn't open output file"), &fixtures).unwrap();
+}
+
+/// How to read the training data, set by the `--format`, `--columns`, `--duplicates-report` and `--strict` flags
+struct DataOptions {
+    /// Overrides the file extension
+    format: Option<String>,
+    columns: Columns,
+    duplicates_report: Option<PathBuf>,
+    /// Whether invalid colours and rows that can't be read are an error, rather than being skipped
+    strict: bool,
+}
+
+/// Reads a single file, or every file in a manifest. Manifests are recognised by `--format manifest` or a `.manifest.json` extension.
+/// Also returns the rows which were left out, see `formats::read`
+fn read_training_data(path: &Path, format: &DataOptions) -> (TrainingDataRaw, Vec<Skipped>) {
+    if format.format.as_deref() == Some("manifest") || path.to_string_lossy().ends_with(".manifest.json") {
+        return manifest::load(path);
+    }
+    let reader = formats::reader_for(path, format.format.as_deref(), format.columns.clone()).unwrap_or_else(|e| panic!("{e}"));
+    return formats::read(path, reader.as_ref());
+}
+
+/// Reads the training data, leaves out unreadable rows and invalid colours and deals with duplicate names according to the config.
+/// Exits if the options or config say those are an error
+fn load_training_data(path: &Path, options: &DataOptions, config: &Config) -> TrainingDataRaw {
+    let (data, skipped) = read_training_data(path, options);
+    if !skipped.is_empty() {
+        println!("{} rows can't be read", skipped.len());
+        for row in &skipped {
+            println!("  {row}");
+        }
+        if options.strict {
+            println!("Refusing to train, some rows can't be read");
+            std::process::exit(1);
+        }
+        println!("These rows are left out, pass --strict to stop instead");
+    }
+
+    let (data, invalid) = check_colors(data);
+    if !invalid.is_empty() {
+        println!("{} entries have a colour that can't be parsed", invalid.len());
+        for entry in &invalid {
+            let location = match (&entry.source, entry.line) {
+                (Some(source), Some(line)) => format!(" (from {source}:{line})"),
+                (Some(source), None) => format!(" (from {source})"),
+                (None, Some(line)) => format!(" (line {line})"),
+                (None, None) => String::new(),
+            };
+            println!("  {:?}{location}: {}", entry.name, entry.error);
+        }
+        if options.strict {
+            println!("Refusing to train, some colours are invalid");
+            std::process::exit(1);
+        }
+        println!("These entries are left out, pass --strict to stop instead");
+    }
+
+    let (data, report) = match duplicates::deduplicate(data, config) {
+        Ok((data, report)) => (Some(data), report),
+        Err(report) => (None, report),
+    };
+
+    println!("Found {} names with duplicates, {} of which have conflicting colours ({:?} policy)", report.groups.len(), report.conflicts(), report.policy);
+    for group in report.groups.iter().filter(|g| g.conflict).take(10) {
+        let entries: Vec<_> = group.entries.iter().map(|e| format!("{:?} {}", e.name, e.color)).collect();
+        println!("  {} apart: {}", group.distance, entries.join(", "));
+    }
+    if let Some(report_file) = &options.duplicates_report {
+        serde_json::to_writer_pretty(File::create(report_file).expect("Couldn't open duplicates report file"), &report).unwrap();
+    }
+
+    let Some(data) = data else {
+        println!("Refusing to train, some names have conflicting colours");
+        std::process::exit(1);
+    };
+    return data;
+}
+
+/// Removes `flag` from the arguments, returning whether it was there
+fn take_switch(args: &mut Vec<OsString>, flag: &str) -> bool {
+    let Some(i) = args.iter().position(|a| a == flag) else {
+        return false;
+    };
+    args.remove(i);
+    return true;
+}
+
+/// Removes `flag` and the value following it from the arguments
+fn take_flag(args: &mut Vec<OsString>, flag: &str) -> Option<OsString> {
+    let i = args.iter().position(|a| a == flag)?;
+    assert!(i + 1 < args.len(), "{flag} needs a value");
+    args.remove(i);
+    return Some(args.remove(i));
+}
+
+/// Prints what the trainer is going to allocate, and exits if it won't fit on the gpu
+fn check_fits(gpu: &GpuDeviceData, config: &Config, training_len: usize, checking_len: usize) {
+    let preflight = preflight::check(gpu, config, training_len, checking_len);
+    print!("{preflight}");
+    if !preflight.is_ok() {
+        println!("Refusing to train, the configuration is too large for this device");
+        std::process::exit(1);
+    }
+}
@@ -30,8 +30,19 @@ pub fn ceil_div(a: u32, b: u64) -> u32 {
     (a as f64 / b as f64).ceil() as u32
 }
 
-pub fn floor_div(a: usize, b: usize) -> usize {
-    (a as f64 / b as f64).floor() as usize
+/// Formats an amount of bytes for humans, eg. "1.5 MiB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{bytes} B");
+    }
+    return format!("{size:.1} {}", UNITS[unit]);
 }
 
 macro_rules! bind_group_layout {
@@ -1,25 +1,25 @@
-
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display, time::Instant};
 
 use wgpu::{BindGroup, BufferDescriptor, BufferUsages, CommandEncoderDescriptor};
 
-use crate::{color::Color, gpu::GpuDeviceData, input::Config, layer::{LayerParameters, LayerValues, MainType, WeightsAndBiases}, misc::{bind_group, size_of, SliceExtension}, shaders::ShaderSet, string::string_to_data, training_data::{DataSet, TrainingData}};
+use crate::{color::Color, gpu::GpuDeviceData, input::Config, layer::{self, BackwardsBuffers, ForwardsBuffers, InputBuffers, LayerInput, LayerPass, LayerValues, MainType, Network}, misc::{bind_group, size_of, SliceExtension}, shaders::{self, StandardShaderPipeline}, string::string_to_data, training_data::{DataSet, Source, TrainingData}};
 
-pub async fn train_nn(gpu: &GpuDeviceData, data: TrainingData, config: Config) -> WeightsAndBiases {
-    // Init buffers for weights and biases
-    let mut network_parameters = Vec::<LayerParameters>::default();
+pub async fn train_nn(gpu: &GpuDeviceData, data: TrainingData, config: Config) -> Network {
+    // Init the layers, including the buffers for their parameters
+    let mut network = Network::default();
     for layer in config.layers() {
-        network_parameters.push(LayerParameters::create(layer.previous_size, layer.size, &gpu.device));
+        network.push(layer::create(layer, &gpu.device));
     }
     assert!(data.training.len() > 0, "No training data");
 
-    let bench_resources = EvalResources::init(gpu, &config, &network_parameters, &data.checking);
-    let resources = TrainingResources::init(&gpu, config, &network_parameters, &data.training);
+    let bench_resources = EvalResources::init(gpu, &config, &network, &data.checking);
+    let resources = TrainingResources::init(&gpu, config, &network, &data.training);
 
-    let mut performance = eval_performance(&data.training, &gpu, &resources.config, &resources.eval_resources).await;
-    let mut bench_performance = eval_performance(&data.checking, &gpu, &resources.config, &bench_resources).await;
+    let mut performance = eval_performance(&data.training, &gpu, &resources.eval_resources).await;
+    let mut bench_performance = eval_performance(&data.checking, &gpu, &bench_resources).await;
 
     let iterations_per_step = 500;
+    assert_eq!(iterations_per_step % ITERATIONS_PER_SUBMISSION, 0);
 
     println!("Benchmark on {} points {bench_performance}", bench_performance.datapoints);
     println!("Training performance on {} points {performance}", performance.datapoints);
@@ -29,19 +29,19 @@ pub async fn train_nn(gpu: &GpuDeviceData, data: TrainingData, config: Config) -
 
     gpu.device.poll(wgpu::MaintainBase::Wait);
     gpu.device.start_capture();
-    run_training_step(&gpu, &resources);
+    run_training_steps(&gpu, &resources, 1);
     gpu.device.stop_capture();
 
     let mut stagnated_performance = 0;
     loop {
-        for _ in 0..iterations_per_step {
-            run_training_step(&gpu, &resources);
+        for _ in 0..(iterations_per_step / ITERATIONS_PER_SUBMISSION) {
+            run_training_steps(&gpu, &resources, ITERATIONS_PER_SUBMISSION);
         }
     
         gpu.device.poll(wgpu::MaintainBase::Wait);
 
-        let next_performance = eval_performance(&data.training, &gpu, &resources.config, &resources.eval_resources).await;
-        let next_bench_performance = eval_performance(&data.checking, &gpu, &resources.config, &bench_resources).await;
+        let next_performance = eval_performance(&data.training, &gpu, &resources.eval_resources).await;
+        let next_bench_performance = eval_performance(&data.checking, &gpu, &bench_resources).await;
 
         println!("{next_bench_performance} Benchmark on {} points", next_bench_performance.datapoints);
         println!("{next_performance} Training performance on {} points", next_performance.datapoints);
@@ -53,6 +53,12 @@ pub async fn train_nn(gpu: &GpuDeviceData, data: TrainingData, config: Config) -
         }
         if stagnated_performance == 2 {
             println!("Training performance stagnated for two iterations in a row, stopping training");
+            if next_bench_performance.by_source.len() > 1 || next_performance.by_source.len() > 1 {
+                println!("Benchmark by source:");
+                next_bench_performance.print_by_source();
+                println!("Training performance by source:");
+                next_performance.print_by_source();
+            }
             break;
         }
 
@@ -60,40 +66,67 @@ pub async fn train_nn(gpu: &GpuDeviceData, data: TrainingData, config: Config) -
         bench_performance = next_bench_performance;
     }
 
-    return network_parameters;
+    return network;
+}
+
+/// Measures how many training iterations per second this device can do on the given data.
+/// Returns the result when each iteration is submitted on its own, and when they're submitted in batches
+pub async fn benchmark_training(gpu: &GpuDeviceData, data: &DataSet, config: Config, iterations: usize) -> (f64, f64) {
+    let mut network = Network::default();
+    for layer in config.layers() {
+        network.push(layer::create(layer, &gpu.device));
+    }
+    let resources = TrainingResources::init(gpu, config, &network, data);
+
+    // Warm up, so the first submission doesn't count pipeline creation and such
+    run_training_steps(gpu, &resources, 1);
+    gpu.device.poll(wgpu::MaintainBase::Wait);
+
+    let measure = |iterations_per_submission: usize| {
+        let start = Instant::now();
+        for _ in 0..(iterations / iterations_per_submission) {
+            run_training_steps(gpu, &resources, iterations_per_submission);
+        }
+        gpu.device.poll(wgpu::MaintainBase::Wait);
+        return (iterations / iterations_per_submission * iterations_per_submission) as f64 / start.elapsed().as_secs_f64();
+    };
+
+    return (measure(1), measure(ITERATIONS_PER_SUBMISSION));
 }
 
-fn run_training_step(gpu: &GpuDeviceData, resources: &TrainingResources) {
-    let config = &resources.config;
+/// The amount of training iterations recorded into a single command buffer.
+/// Submitting is pretty expensive, but a command buffer that runs for too long might trip the driver's timeout
+pub const ITERATIONS_PER_SUBMISSION: usize = 50;
+
+fn run_training_steps(gpu: &GpuDeviceData, resources: &TrainingResources, iterations: usize) {
     let eval_resources = &resources.eval_resources;
-    let shaders = &eval_resources.shaders;
 
-    let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Performance evaluation") });
+    let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Training") });
+    let mut pass = commands.begin_compute_pass(&Default::default());
 
-    // Run the NN forwards on the data
-    for layer in 0..config.num_layers() {
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &eval_resources.bind_groups[layer], &[]);
-        shaders[layer].compute_forwards.setup_pass(&mut pass);
-    }
+    for _ in 0..iterations {
+        for (eval_chunk, chunk) in Iterator::zip(eval_resources.chunks.iter(), resources.chunks.iter()) {
+            // Run the NN forwards on the data
+            for layer in &eval_chunk.forwards {
+                layer.record(&mut pass);
+            }
 
-    // Run the backpropagation steps
-    for layer in (0..config.num_layers()).rev() {
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &resources.backprop_bind_groups[layer], &[]);
-        shaders[layer].backpropagation.setup_pass(&mut pass);
-    }
+            // Compute the derivatives of the final layer from the cost function
+            pass.set_bind_group(0, &chunk.backprop_start.1, &[]);
+            chunk.backprop_start.0.setup_pass(&mut pass);
 
-    // Apply backpropagation steps
-    for layer in 0..config.num_layers() {
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &resources.backprop_bias_apply_bind_groups[layer], &[]);
-        shaders[layer].apply_backprop_biases.setup_pass(&mut pass);
-        drop(pass);
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &resources.backprop_weight_apply_bind_groups[layer], &[]);
-        shaders[layer].apply_backprop_weights.setup_pass(&mut pass);
+            // Run the backpropagation steps, each layer will also add the derivatives of its own parameters to the gradients
+            for layer in chunk.backwards.iter().rev() {
+                layer.record(&mut pass);
+            }
+        }
+
+        // Apply the gradients, now that every chunk has been added to them
+        for layer in &resources.apply_gradients {
+            layer.record(&mut pass);
+        }
     }
+    drop(pass);
 
     // Submit everything
     gpu.queue.submit([commands.finish()]);
@@ -108,16 +141,16 @@ pub fn calc_cost(expected: Color, actual: Color) -> MainType {
 
 pub async fn eval_single(data: &str, gpu: &GpuDeviceData, config: &Config, resources: &EvalResources) -> Color {
     let data = string_to_data(data, config);
-    let input = resources.a_buffers.buffers.first().unwrap();
-    gpu.queue.write_buffer(input, 0, bytemuck::cast_slice(&data));
+    let chunk = &resources.chunks[0];
+    gpu.queue.write_buffer(&chunk.input.active, 0, bytemuck::cast_slice(&data));
 
     // Run the NN forwards on the data
     let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Performance evaluation") });
-    for layer in 0..config.num_layers() {
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &resources.bind_groups[layer], &[]);
-        resources.shaders[layer].compute_forwards.setup_pass(&mut pass);
+    let mut pass = commands.begin_compute_pass(&Default::default());
+    for layer in &chunk.forwards {
+        layer.record(&mut pass);
     }
+    drop(pass);
     let output = resources.a_buffers.read_output(gpu, &mut commands);
     gpu.queue.submit([commands.finish()]);
 
@@ -133,17 +166,7 @@ pub async fn eval_single(data: &str, gpu: &GpuDeviceData, config: &Config, resou
     return output_color;
 }
 
-pub async fn eval_performance(data: &DataSet, gpu: &GpuDeviceData, config: &Config, resources: &EvalResources) -> PerformanceEval {
-    // Run the NN forwards on the data
-    let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Performance evaluation") });
-    for layer in 0..config.num_layers() {
-        let mut pass = commands.begin_compute_pass(&Default::default());
-        pass.set_bind_group(0, &resources.bind_groups[layer], &[]);
-        resources.shaders[layer].compute_forwards.setup_pass(&mut pass);
-    }
-    let output = resources.a_buffers.read_output(gpu, &mut commands);
-    gpu.queue.submit([commands.finish()]);
-
+pub async fn eval_performance(data: &DataSet, gpu: &GpuDeviceData, resources: &EvalResources) -> PerformanceEval {
     // Process NN results!
     let mut total_cost = 0f64;
     let mut count = 0;
@@ -152,26 +175,44 @@ pub async fn eval_performance(data: &DataSet, gpu: &GpuDeviceData, config: &Conf
     let white = Color::from_rgb((0.0, 0.0, 0.0));
     let mut vmin = MainType::MAX;
     let mut vmax = MainType::MIN;
-    
-    let output_buf = &output;
-    {
-        output_buf.slice(..).map_buffer(&gpu.device, wgpu::MapMode::Read).await.unwrap();
-        let outputs = output_buf.slice(..).get_mapped_range();
-        let outputs: &[Color] = bytemuck::cast_slice(&outputs);
-        
-        Iterator::zip(data.iter().map(|data| data.1), outputs)
-            .for_each(|(expected, nn_output)| {
-                let cost = calc_cost(expected, *nn_output);
-                total_cost += cost as f64;
-                count += 1;
-                min = min.min(cost);
-                max = max.max(cost);
-                let variance = calc_cost(white, *nn_output);
-                vmin = vmin.min(variance);
-                vmax = vmax.max(variance);
-            });
+    let mut by_source = BTreeMap::<Source, (usize, f64)>::new();
+
+    // The chunks share their buffers, so each one needs to be read before the next one can run
+    for (chunk, data) in Iterator::zip(resources.chunks.iter(), data.chunks(resources.chunk_size)) {
+        // Run the NN forwards on the data
+        let mut commands = gpu.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Performance evaluation") });
+        let mut pass = commands.begin_compute_pass(&Default::default());
+        for layer in &chunk.forwards {
+            layer.record(&mut pass);
+        }
+        drop(pass);
+        let output = resources.a_buffers.read_output(gpu, &mut commands);
+        gpu.queue.submit([commands.finish()]);
+
+        let output_buf = &output;
+        {
+            output_buf.slice(..).map_buffer(&gpu.device, wgpu::MapMode::Read).await.unwrap();
+            let outputs = output_buf.slice(..).get_mapped_range();
+            let outputs: &[Color] = bytemuck::cast_slice(&outputs);
+
+            // The buffer is sized for the largest chunk, the zip ignores anything past the end of this one
+            Iterator::zip(data.iter(), outputs)
+                .for_each(|((_, expected, source), nn_output)| {
+                    let cost = calc_cost(*expected, *nn_output);
+                    total_cost += cost as f64;
+                    let source = by_source.entry(source.clone()).or_default();
+                    source.0 += 1;
+                    source.1 += cost as f64;
+                    count += 1;
+                    min = min.min(cost);
+                    max = max.max(cost);
+                    let variance = calc_cost(white, *nn_output);
+                    vmin = vmin.min(variance);
+                    vmax = vmax.max(variance);
+                });
+        }
+        output_buf.unmap();
     }
-    output_buf.unmap();
     assert_eq!(count, resources.invocations);
 
     return PerformanceEval {
@@ -181,6 +222,7 @@ pub async fn eval_performance(data: &DataSet, gpu: &GpuDeviceData, config: &Conf
         max_err: max,
         spread_min: vmin,
         spread_max: vmax,
+        by_source: by_source.into_iter().map(|(source, (count, total))| (source, (count, total / count as f64))).collect(),
     };
 }
 
@@ -194,6 +236,18 @@ pub struct PerformanceEval {
     // Really these are just used to detect if the network is outputting the same values for everything.
     pub spread_min: MainType,
     pub spread_max: MainType,
+
+    /// The amount of datapoints and the average error of each source
+    pub by_source: BTreeMap<Source, (usize, f64)>,
+}
+
+impl PerformanceEval {
+    pub fn print_by_source(&self) {
+        for (source, (datapoints, avg_err)) in &self.by_source {
+            let source = if source.is_empty() { "(unknown)" } else { source };
+            println!("  {source}: avg {avg_err} on {datapoints} points");
+        }
+    }
 }
 
 impl Display for PerformanceEval {
@@ -204,131 +258,246 @@ impl Display for PerformanceEval {
 
 /// Resources used during training. Superset of resources used during evals.
 struct TrainingResources {
-    config: Config,
     deriv_z_buffers: LayerValues,
     eval_resources: EvalResources,
-    backprop_bind_groups: Vec<BindGroup>,
-    backprop_bias_apply_bind_groups: Vec<BindGroup>,
-    backprop_weight_apply_bind_groups: Vec<BindGroup>,
+    /// The backpropagation passes for each chunk of the data, see `EvalResources::chunks`
+    chunks: Vec<TrainingChunk>,
+    /// Applies the gradients to the parameters of each layer. The derivatives of every chunk are added up in the
+    /// gradients first, even if there's only one chunk, so the result doesn't depend on how the data was split
+    apply_gradients: Vec<Box<dyn LayerPass>>,
+}
+
+struct TrainingChunk {
+    /// Computes the derivatives of the final layer
+    backprop_start: (StandardShaderPipeline, BindGroup),
+    /// Backpropagation passes for each layer, these should be run in reverse order
+    backwards: Vec<Box<dyn LayerPass>>,
 }
 
 impl TrainingResources {
-    fn init(gpu: &GpuDeviceData, config: Config, parameters: &WeightsAndBiases, data: &DataSet) -> Self {
-        let invocations = data.len();
+    fn init(gpu: &GpuDeviceData, config: Config, network: &Network, data: &DataSet) -> Self {
+        let max_invocations = max_chunk_invocations(gpu, &config);
+        return Self::init_chunked(gpu, config, network, data, max_invocations);
+    }
+
+    /// Like `init`, but splits the data into chunks of at most `max_invocations`
+    fn init_chunked(gpu: &GpuDeviceData, config: Config, network: &Network, data: &DataSet, max_invocations: usize) -> Self {
         // Resources needed to run the nn on the `training` dataset
-        let eval_resources = EvalResources::init(gpu, &config, parameters, &data);
-
-        let deriv_z_buffers = LayerValues::create(&gpu, &config, invocations);
-        let expected_values_buf = gpu.device.create_buffer(&BufferDescriptor {
-            label: Some("nn expected outputs"),
-            size: config.layers().last().unwrap().size * invocations as u64 * size_of::<MainType>(),
-            usage: BufferUsages::STORAGE,
-            mapped_at_creation: true
-        });
-        {
-            // Copy all of the expected values into the buffer
-            let expected_values = &mut expected_values_buf.slice(..).get_mapped_range_mut();
-            let expected_values: &mut [Color] = bytemuck::cast_slice_mut(expected_values);
-            Iterator::zip(expected_values.iter_mut(), data).for_each(|(gpu_entry, data_entry)| *gpu_entry = data_entry.1);
-        }
-        expected_values_buf.unmap();
-
-        let mut bind_groups = Vec::new();
-        // Create bind groups for all but the final layer
-        // (the layers don't include the input layer, but some of the buffers do. That's why the indexing is so awkward)
-        for layer in 0..(config.num_layers() - 1) {
-            bind_groups.push(gpu.device.create_bind_group(&bind_group! {
-                &gpu.shader_components.backpropagation.0,
-                0 => &parameters[layer + 1].weights,
-                1 => &eval_resources.z_buffers.buffers[layer + 1],
-                2 => &deriv_z_buffers.buffers[layer + 2],
-                3 => &deriv_z_buffers.buffers[layer + 1],
-            }));
-        }
-        // Create the bind groups for the final layer
+        let eval_resources = EvalResources::init_chunked(gpu, &config, network, &data, max_invocations);
+        let deriv_z_buffers = LayerValues::create(&gpu, &config, eval_resources.chunk_size);
+
+        let gradients: Vec<_> = network.iter().map(|layer| layer.create_gradients(gpu)).collect();
+
         let last_layer_index = config.num_layers() - 1;
-        bind_groups.push(gpu.device.create_bind_group(&bind_group! {
-            &gpu.shader_components.backpropagation_start.0,
-            0 => &eval_resources.a_buffers.buffers[last_layer_index+1],
-            1 => &eval_resources.z_buffers.buffers[last_layer_index+1],
-            2 => &expected_values_buf,
-            3 => &deriv_z_buffers.buffers[last_layer_index+1]
-        }));
-
-        // Create other bind groups
-        let mut bias_apply_bind_groups = Vec::new();
-        for layer in 0..config.num_layers() {
-            bias_apply_bind_groups.push(gpu.device.create_bind_group(&bind_group! {
-                &eval_resources.shaders[layer].apply_backprop_biases.get_layout(),
-                0 => &deriv_z_buffers.buffers[layer + 1],
-                1 => &parameters[layer].biases,
-            }));
-        }
-        let mut weight_apply_bind_groups = Vec::new();
-        for layer in 0..config.num_layers() {
-            weight_apply_bind_groups.push(gpu.device.create_bind_group(&bind_group! {
-                &eval_resources.shaders[layer].apply_backprop_weights.get_layout(),
-                0 => &eval_resources.a_buffers.buffers[layer],
-                1 => &deriv_z_buffers.buffers[layer + 1],
-                2 => &parameters[layer].weights,
-            }));
+        let mut chunks = Vec::new();
+        for (eval_chunk, data) in Iterator::zip(eval_resources.chunks.iter(), data.chunks(eval_resources.chunk_size)) {
+            let invocations = eval_chunk.invocations;
+            let expected_values_buf = gpu.device.create_buffer(&BufferDescriptor {
+                label: Some("nn expected outputs"),
+                size: config.layers().last().unwrap().size * invocations as u64 * size_of::<MainType>(),
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: true
+            });
+            {
+                // Copy all of the expected values into the buffer
+                let expected_values = &mut expected_values_buf.slice(..).get_mapped_range_mut();
+                let expected_values: &mut [Color] = bytemuck::cast_slice_mut(expected_values);
+                Iterator::zip(expected_values.iter_mut(), data).for_each(|(gpu_entry, data_entry)| *gpu_entry = data_entry.1);
+            }
+            expected_values_buf.unmap();
+
+            // Create the pass for the final layer
+            let backprop_start = shaders::compile_backpropagation_start(gpu, config.layers()[last_layer_index].size, invocations);
+            let backprop_start_bind_group = gpu.device.create_bind_group(&bind_group! {
+                &gpu.shader_components.backpropagation_start.0,
+                0 => &eval_resources.a_buffers.buffers[last_layer_index],
+                1 => &eval_resources.z_buffers.buffers[last_layer_index],
+                2 => &expected_values_buf,
+                3 => &deriv_z_buffers.buffers[last_layer_index]
+            });
+
+            let mut backwards = Vec::new();
+            for (i, layer) in network.iter().enumerate() {
+                backwards.push(layer.backwards(gpu, BackwardsBuffers {
+                    input: eval_resources.layer_input(eval_chunk, i),
+                    input_z: if i == 0 { None } else { Some(&eval_resources.z_buffers.buffers[i - 1]) },
+                    input_deriv_z: if i == 0 { None } else { Some(&deriv_z_buffers.buffers[i - 1]) },
+                    deriv_z: &deriv_z_buffers.buffers[i],
+                    gradients: &gradients[i],
+                }, invocations));
+            }
+
+            chunks.push(TrainingChunk {
+                backprop_start: (backprop_start, backprop_start_bind_group),
+                backwards,
+            });
         }
 
+        let apply_gradients = Iterator::zip(network.iter(), gradients.iter())
+            .map(|(layer, gradients)| layer.apply_gradients(gpu, gradients, eval_resources.invocations))
+            .collect();
+
         Self {
-            config,
             deriv_z_buffers,
             eval_resources,
-            backprop_bind_groups: bind_groups,
-            backprop_bias_apply_bind_groups: bias_apply_bind_groups,
-            backprop_weight_apply_bind_groups: weight_apply_bind_groups,
+            chunks,
+            apply_gradients,
         }
     }
 }
 
+/// The largest amount of invocations that fit in a single chunk of the data.
+/// Every buffer holding values for each invocation needs to fit in the limits of the device.
+pub fn max_chunk_invocations(gpu: &GpuDeviceData, config: &Config) -> usize {
+    let limits = gpu.device.limits();
+    let max_buffer_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+
+    // The size of the largest buffer, per invocation
+    let layers = config.layers();
+    let bytes_per_invocation = layers.iter().map(|layer| layer.size * size_of::<MainType>())
+        // The expected values
+        .chain([layers.last().unwrap().size * size_of::<MainType>()])
+        // The input, and the input grouped by node
+        .chain([config.active_inputs() * size_of::<u32>()])
+        .max().unwrap();
+
+    // The tiled shaders dispatch a workgroup for every `tile_size` invocations
+    let max_workgroups = limits.max_compute_workgroups_per_dimension as u64 * gpu.shader_components.tile_size as u64;
+
+    return (max_buffer_size / bytes_per_invocation).min(max_workgroups).max(1) as usize;
+}
+
+/// Spreads `invocations` evenly over as few chunks as possible. Returns the amount of chunks and the size of each chunk
+/// (the last chunk might be smaller)
+pub fn split_into_chunks(invocations: usize, max_invocations: usize) -> (usize, usize) {
+    if invocations == 0 {
+        return (0, 0);
+    }
+    let chunk_count = invocations.div_ceil(max_invocations);
+    return (chunk_count, invocations.div_ceil(chunk_count));
+}
+
 /// Resources used during evaluations
 pub struct EvalResources {
+    /// Total over all chunks
     invocations: usize,
+    /// The data is split into chunks which each fit into the buffers. Every chunk has
+    /// the same amount of invocations, except the last one which might be smaller
+    chunks: Vec<EvalChunk>,
+    chunk_size: usize,
+    /// These are shared by all chunks, so the chunks have to run one after another
     a_buffers: LayerValues,
     z_buffers: LayerValues,
-    shaders: Vec<ShaderSet>,
-    /// Bind groups for each of the `compute_forwards` invocations
-    bind_groups: Vec<BindGroup>,
+}
+
+struct EvalChunk {
+    invocations: usize,
+    input: InputBuffers,
+    /// The `compute_forwards` passes for each layer
+    forwards: Vec<Box<dyn LayerPass>>,
 }
 
 impl EvalResources {
-    pub fn init(gpu: &GpuDeviceData, config: &Config, parameters: &WeightsAndBiases, data: &DataSet) -> Self {
+    pub fn init(gpu: &GpuDeviceData, config: &Config, network: &Network, data: &DataSet) -> Self {
+        return Self::init_chunked(gpu, config, network, data, max_chunk_invocations(gpu, config));
+    }
+
+    /// Like `init`, but splits the data into chunks of at most `max_invocations`
+    fn init_chunked(gpu: &GpuDeviceData, config: &Config, network: &Network, data: &DataSet, max_invocations: usize) -> Self {
         assert!(!data.is_empty());
 
-        let invocations = data.len();
-        let z_buffers = LayerValues::create(gpu, config, invocations);
-        let a_buffers = LayerValues::create_with_input(gpu, config, invocations, |gpu_input| {
-            Iterator::zip(
-                data.iter().map(|data| &data.0),
-                bytemuck::cast_slice_mut::<_, MainType>(gpu_input).chunks_exact_mut(config.input_length() as usize)
-            ).for_each(|(input_data, gpu_value)| {
-                gpu_value.copy_from_slice(input_data);
-            });
-        });
-        let shaders = ShaderSet::compile(&gpu, config, invocations);
-
-        let mut bind_groups = Vec::new();
-        for layer in 0..config.num_layers() {
-            bind_groups.push(gpu.device.create_bind_group(&bind_group! {
-                &gpu.shader_components.compute_forwards.0,
-                0 => &parameters[layer].weights,
-                1 => &parameters[layer].biases,
-                2 => &a_buffers.buffers[layer],
-                3 => &z_buffers.buffers[layer + 1],
-                4 => &a_buffers.buffers[layer + 1],
-            }));
-        }
+        let (_, chunk_size) = split_into_chunks(data.len(), max_invocations);
 
-        Self {
+        let mut output = Self {
             invocations: data.len(),
-            a_buffers,
-            z_buffers,
-            shaders,
-            bind_groups,
+            chunks: Vec::new(),
+            chunk_size,
+            a_buffers: LayerValues::create(gpu, config, chunk_size),
+            z_buffers: LayerValues::create(gpu, config, chunk_size),
+        };
+
+        for data in data.chunks(chunk_size) {
+            let mut chunk = EvalChunk {
+                invocations: data.len(),
+                input: InputBuffers::create(gpu, config, data),
+                forwards: Vec::new(),
+            };
+            for (i, layer) in network.iter().enumerate() {
+                let pass = layer.forwards(gpu, ForwardsBuffers {
+                    input: output.layer_input(&chunk, i),
+                    output_z: &output.z_buffers.buffers[i],
+                    output_a: &output.a_buffers.buffers[i],
+                }, chunk.invocations);
+                chunk.forwards.push(pass);
+            }
+            output.chunks.push(chunk);
         }
+
+        return output;
     }
-}
\ No newline at end of file
+
+    /// The input of the `i`th layer. This is either the network input or the activations of the layer before it
+    fn layer_input<'a>(&'a self, chunk: &'a EvalChunk, i: usize) -> LayerInput<'a> {
+        if i == 0 {
+            LayerInput::OneHot(&chunk.input)
+        } else {
+            LayerInput::Activations(&self.a_buffers.buffers[i - 1])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::{color::Color, gpu::{open_device, request_adapter}, input::Config, layer::{self, dense::JsonDenseLayer, Network}, string::string_to_data, training_data::DataSet};
+
+    use super::{run_training_steps, TrainingResources};
+
+    #[tokio::test]
+    async fn chunked_training_matches_single_chunk() {
+        let Some(adapter) = request_adapter().await else {
+            eprintln!("No gpu adapter available, skipping test");
+            return;
+        };
+        let gpu = open_device(adapter).await;
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        // Each set of training resources takes its own copy
+        let config = || Config::test(r#"{ "input_length": 8, "percentage_training": 1.0, "layers": [16, 12, 3] }"#);
+
+        let data: DataSet = (0..301).map(|_| {
+            let name: String = (0..rng.gen_range(1..=8)).map(|_| rng.gen_range('a'..='z')).collect();
+            (string_to_data(&name, &config()), Color { l: rng.gen(), a: rng.gen(), b: rng.gen() }, String::new())
+        }).collect();
+
+        // Two copies of the same network
+        let single: Network = config().layers().into_iter().map(|l| layer::create(l, &gpu.device)).collect();
+        let mut chunked = Network::default();
+        for (layer_config, layer) in Iterator::zip(config().layers().into_iter(), single.iter()) {
+            chunked.push(layer::load(layer_config, &gpu.device, &layer.to_json(&gpu).await));
+        }
+
+        let single_resources = TrainingResources::init(&gpu, config(), &single, &data);
+        // Doesn't divide evenly, so the last chunk is smaller
+        let chunked_resources = TrainingResources::init_chunked(&gpu, config(), &chunked, &data, 70);
+        assert_eq!(single_resources.chunks.len(), 1);
+        assert_eq!(chunked_resources.chunks.len(), 5);
+
+        run_training_steps(&gpu, &single_resources, 3);
+        run_training_steps(&gpu, &chunked_resources, 3);
+
+        for (single, chunked) in Iterator::zip(single.iter(), chunked.iter()) {
+            let single: JsonDenseLayer = serde_json::from_value(single.to_json(&gpu).await).unwrap();
+            let chunked: JsonDenseLayer = serde_json::from_value(chunked.to_json(&gpu).await).unwrap();
+            // Only the order of the sums differs
+            let check = |single: &[f32], chunked: &[f32]| {
+                for (i, (single, chunked)) in Iterator::zip(single.iter(), chunked.iter()).enumerate() {
+                    assert!((single - chunked).abs() <= 1e-5, "Mismatch at {i}: single chunk gave {single}, multiple chunks gave {chunked}");
+                }
+            };
+            check(&single.weights, &chunked.weights);
+            check(&single.biases, &chunked.biases);
+        }
+    }
+}
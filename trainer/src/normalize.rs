@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::Config;
+
+/// Cleans up a name before it's encoded. The names scraped from wikipedia contain things like
+/// "(Crayola)", "&amp;" and typographic quotes, which say nothing about the colour.
+/// This runs as part of `encode`, so it's applied the same way during training and when evaluating the network.
+/// This logic MUST match the one in `NNConfig.Normalizer` on the java side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct Normalizer {
+    /// What to do with qualifiers in parentheses which aren't in `qualifier_map`
+    pub qualifiers: Qualifiers,
+    /// Replacements for specific qualifiers. The keys are lowercase and don't include the parentheses,
+    /// an empty replacement removes the qualifier
+    pub qualifier_map: BTreeMap<String, String>,
+    /// Decode html entities such as "&amp;" and "&#233;"
+    pub unescape_entities: bool,
+    /// Replace typographic quotes and dashes with their ascii equivalent, and ellipses with a period
+    pub ascii_punctuation: bool,
+    /// Each of these characters is removed
+    pub strip_characters: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Qualifiers {
+    /// Leave them in, parentheses included
+    #[default]
+    Keep,
+    /// Remove them entirely
+    Strip,
+    /// Keep the text, but remove the parentheses
+    Unwrap,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self {
+            qualifiers: Qualifiers::Keep,
+            qualifier_map: BTreeMap::new(),
+            unescape_entities: false,
+            ascii_punctuation: false,
+            strip_characters: String::new(),
+        }
+    }
+}
+
+impl Normalizer {
+    /// Applies every step in order: entities, qualifiers, punctuation, stripping, and finally collapsing whitespace
+    pub fn normalize(&self, str: &str) -> String {
+        let mut str = str.to_owned();
+        if self.unescape_entities {
+            str = unescape_entities(&str);
+        }
+        if self.qualifiers != Qualifiers::Keep || !self.qualifier_map.is_empty() {
+            str = self.replace_qualifiers(&str);
+        }
+        if self.ascii_punctuation {
+            str = str.chars().map(ascii_punctuation).collect();
+        }
+        if !self.strip_characters.is_empty() {
+            str = str.chars().filter(|c| !self.strip_characters.contains(*c)).collect();
+        }
+        return itertools::join(str.split_whitespace(), " ");
+    }
+
+    fn replace_qualifiers(&self, str: &str) -> String {
+        let mut output = String::with_capacity(str.len());
+        let mut rest = str;
+        while let Some(start) = rest.find('(') {
+            // An unclosed parenthesis isn't a qualifier
+            let Some(end) = closing_paren(&rest[start..]).map(|e| start + e) else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+            let inner = &rest[start + 1..end];
+            let replacement = match self.qualifier_map.get(&inner.trim().to_lowercase()) {
+                Some(replacement) => Some(replacement.as_str()),
+                None => match self.qualifiers {
+                    Qualifiers::Keep => None,
+                    Qualifiers::Strip => Some(""),
+                    Qualifiers::Unwrap => Some(inner),
+                },
+            };
+            match replacement {
+                // Keep the replacement from sticking to the words around it
+                Some(replacement) => {
+                    output.push(' ');
+                    output.push_str(replacement);
+                    output.push(' ');
+                }
+                None => output.push_str(&rest[start..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        return output;
+    }
+}
+
+/// Cleans up a name the same way the input encoding does, and lowercases it
+pub fn prepare(name: &str, config: &Config) -> String {
+    return config.alphabet.normalize(&config.normalize.normalize(name)).to_lowercase();
+}
+
+/// The byte index of the parenthesis closing the one at the start of `str`
+fn closing_paren(str: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in str.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    return None;
+}
+
+/// Decodes named and numeric html entities. Anything that isn't a valid entity is left as is
+fn unescape_entities(str: &str) -> String {
+    let mut output = String::with_capacity(str.len());
+    let mut rest = str;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+        match decoded {
+            Some((c, end)) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    return output;
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    return match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => None,
+    };
+}
+
+fn ascii_punctuation(c: char) -> char {
+    return match c {
+        '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{2032}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{2033}' => '"',
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2212}' => '-',
+        '\u{2026}' => '.',
+        c => c,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Normalizer, Qualifiers};
+
+    fn normalizer(qualifiers: Qualifiers) -> Normalizer {
+        Normalizer {
+            qualifiers,
+            qualifier_map: [("pantone".to_owned(), "".to_owned()), ("web".to_owned(), "web colour".to_owned())].into(),
+            unescape_entities: true,
+            ascii_punctuation: true,
+            strip_characters: "#".to_owned(),
+        }
+    }
+
+    #[test]
+    fn default_only_collapses_whitespace() {
+        let normalizer = Normalizer::default();
+        assert_eq!(normalizer.normalize("  Blue  (Crayola)&amp; #1 "), "Blue (Crayola)&amp; #1");
+    }
+
+    #[test]
+    fn replaces_qualifiers() {
+        assert_eq!(normalizer(Qualifiers::Keep).normalize("Blue (Crayola)"), "Blue (Crayola)");
+        assert_eq!(normalizer(Qualifiers::Strip).normalize("Blue (Crayola)"), "Blue");
+        assert_eq!(normalizer(Qualifiers::Unwrap).normalize("Blue(Crayola)"), "Blue Crayola");
+        // The map takes precedence
+        assert_eq!(normalizer(Qualifiers::Keep).normalize("Blue (Pantone) green"), "Blue green");
+        assert_eq!(normalizer(Qualifiers::Strip).normalize("Blue ( Web )"), "Blue web colour");
+        // Nested and unclosed parentheses
+        assert_eq!(normalizer(Qualifiers::Strip).normalize("Blue (a (b) c) green (d"), "Blue green (d");
+    }
+
+    #[test]
+    fn unescapes_entities() {
+        let normalizer = Normalizer { strip_characters: String::new(), ..normalizer(Qualifiers::Keep) };
+        assert_eq!(normalizer.normalize("Black &amp; white"), "Black & white");
+        assert_eq!(normalizer.normalize("Caf&#233; au lait"), "Café au lait");
+        assert_eq!(normalizer.normalize("Caf&#xE9;&nbsp;noir"), "Café noir");
+        // Only decoded once, and invalid entities are left alone
+        assert_eq!(normalizer.normalize("&amp;lt; & &bogus; &#xD800;"), "&lt; & &bogus; &#xD800;");
+    }
+
+    #[test]
+    fn normalizes_punctuation() {
+        let normalizer = normalizer(Qualifiers::Keep);
+        assert_eq!(normalizer.normalize("Robin\u{2019}s egg \u{2013} #1"), "Robin's egg - 1");
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt::Display;
+
+use wgpu::Features;
+
+use crate::{gpu::GpuDeviceData, input::Config, layer::{MainType, Size}, misc::{format_bytes, size_of}, neural_network::{max_chunk_invocations, split_into_chunks}, shaders::{self, STD_WORKGROUP_SIZE}};
+
+/// Everything the trainer is going to allocate and dispatch for a config, checked against the limits of the device.
+/// This doesn't allocate anything itself, so a config which won't fit can be refused before wgpu panics halfway through.
+pub struct Preflight {
+    buffers: Vec<BufferEstimate>,
+    problems: Vec<String>,
+}
+
+struct BufferEstimate {
+    name: String,
+    /// In bytes
+    size: Size,
+    /// How many of these buffers there are
+    count: usize,
+    /// Storage buffers have a lower size limit, as they have to fit in a single binding
+    storage: bool,
+}
+
+impl Preflight {
+    /// True if everything fits in the device limits
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The estimated amount of gpu memory used by all buffers together
+    pub fn total_memory(&self) -> Size {
+        self.buffers.iter().map(|b| b.size * b.count as Size).sum()
+    }
+
+    fn buffer(&mut self, name: String, size: Size, count: usize, storage: bool) {
+        if count > 0 {
+            self.buffers.push(BufferEstimate { name, size, count, storage });
+        }
+    }
+
+    fn dispatch(&mut self, max_workgroups: u32, name: String, workgroups: [Size; 3]) {
+        if let Some(n) = workgroups.iter().find(|n| **n > max_workgroups as Size) {
+            self.problems.push(format!("{name} dispatches {n} workgroups along one axis, but the device allows at most {max_workgroups}"));
+        }
+    }
+}
+
+/// Works out the buffers and dispatches needed to train on `training_len` entries (and evaluate on `checking_len` entries)
+pub fn check(gpu: &GpuDeviceData, config: &Config, training_len: usize, checking_len: usize) -> Preflight {
+    let limits = gpu.device.limits();
+    let mappable = gpu.device.features().contains(Features::MAPPABLE_PRIMARY_BUFFERS);
+    let value_size = size_of::<MainType>();
+    let layers = config.layers();
+    let last_layer = layers.last().unwrap();
+
+    let mut output = Preflight {
+        buffers: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    // Should match `TrainingResources::init` and `EvalResources::init`
+    let max_invocations = max_chunk_invocations(gpu, config);
+    let (training_chunks, training_chunk_size) = split_into_chunks(training_len, max_invocations);
+    let (checking_chunks, checking_chunk_size) = split_into_chunks(checking_len, max_invocations);
+    let chunk_size = training_chunk_size.max(checking_chunk_size) as Size;
+
+    for (i, layer) in layers.iter().enumerate() {
+        let weights = layer.previous_size * layer.size * value_size;
+        let biases = layer.size * value_size;
+        output.buffer(format!("layer {i} weights"), weights, 1, true);
+        output.buffer(format!("layer {i} biases"), biases, 1, true);
+        output.buffer(format!("layer {i} weight gradients"), weights, 1, true);
+        output.buffer(format!("layer {i} bias gradients"), biases, 1, true);
+        if !mappable {
+            output.buffer(format!("layer {i} parameter staging"), weights + biases, 1, false);
+        }
+
+        output.buffer(format!("layer {i} z, a and derivZ (training)"), layer.size * training_chunk_size as Size * value_size, 3, true);
+        output.buffer(format!("layer {i} z and a (checking)"), layer.size * checking_chunk_size as Size * value_size, if checking_chunks > 0 { 2 } else { 0 }, true);
+
+        // Every chunk of the training data has its own apply pipelines
+        let (apply_chunks, partial_sums) = if layer.active_inputs.is_some() {
+            shaders::apply_backprop_biases_chunks(gpu, layer.size, training_chunk_size.max(1))
+        } else {
+            shaders::apply_backprop_weights_chunks(gpu, layer.previous_size, layer.size, training_chunk_size.max(1))
+        };
+        output.buffer(format!("layer {i} partial sums"), partial_sums, training_chunks, true);
+
+        // Should match the `setup_pass` functions in shaders/mod.rs
+        let tile = gpu.shader_components.tile_size as Size;
+        let max = limits.max_compute_workgroups_per_dimension;
+        if layer.active_inputs.is_some() {
+            output.dispatch(max, format!("layer {i} compute_forwards_one_hot"), [chunk_size.div_ceil(STD_WORKGROUP_SIZE.0), layer.size.div_ceil(STD_WORKGROUP_SIZE.1), 1]);
+            output.dispatch(max, format!("layer {i} apply_backprop_biases"), [apply_chunks as Size, layer.size.div_ceil(8), 1]);
+            output.dispatch(max, format!("layer {i} apply_backprop_weights_one_hot"), [layer.previous_size.div_ceil(8), layer.size.div_ceil(8), 1]);
+        } else {
+            output.dispatch(max, format!("layer {i} compute_forwards"), [chunk_size.div_ceil(tile), layer.size.div_ceil(tile), 1]);
+            output.dispatch(max, format!("layer {i} backpropagation"), [chunk_size.div_ceil(tile), layer.previous_size.div_ceil(tile), 1]);
+            output.dispatch(max, format!("layer {i} apply_backprop_weights"), [apply_chunks as Size, (layer.previous_size + 1).div_ceil(8), layer.size]);
+        }
+        if i == layers.len() - 1 {
+            output.dispatch(max, format!("layer {i} backpropagation_start"), [chunk_size.div_ceil(STD_WORKGROUP_SIZE.0), layer.size.div_ceil(STD_WORKGROUP_SIZE.1), 1]);
+        }
+    }
+
+    let active_inputs = config.active_inputs();
+    for (name, chunks, chunk_size) in [("training", training_chunks, training_chunk_size), ("checking", checking_chunks, checking_chunk_size)] {
+        let chunk_size = chunk_size as Size;
+        output.buffer(format!("{name} input"), active_inputs * chunk_size * size_of::<u32>(), chunks, true);
+        output.buffer(format!("{name} input column offsets"), (config.input_length() + 1) * size_of::<u32>(), chunks, true);
+        // At most, every input node is active
+        output.buffer(format!("{name} input column invocations"), active_inputs * chunk_size * size_of::<u32>(), chunks, true);
+        if !mappable && chunks > 0 {
+            output.buffer(format!("{name} output staging"), last_layer.size * chunk_size * value_size, 1, false);
+        }
+    }
+    output.buffer("expected outputs".to_owned(), last_layer.size * training_chunk_size as Size * value_size, training_chunks, true);
+
+    let max_binding = limits.max_storage_buffer_binding_size as Size;
+    for buffer in &output.buffers {
+        if buffer.size > limits.max_buffer_size {
+            output.problems.push(format!("{} needs {}, but the device allows at most {} per buffer", buffer.name, format_bytes(buffer.size), format_bytes(limits.max_buffer_size)));
+        } else if buffer.storage && buffer.size > max_binding {
+            output.problems.push(format!("{} needs {}, but the device can bind at most {} as a storage buffer", buffer.name, format_bytes(buffer.size), format_bytes(max_binding)));
+        }
+    }
+
+    for (shader, size, storage) in shaders::workgroup_sizes(gpu) {
+        let fits = size[0] <= limits.max_compute_workgroup_size_x
+            && size[1] <= limits.max_compute_workgroup_size_y
+            && size[2] <= limits.max_compute_workgroup_size_z
+            && size.iter().product::<u32>() <= limits.max_compute_invocations_per_workgroup;
+        if !fits {
+            output.problems.push(format!("{shader} uses workgroups of {size:?}, which is too large for the device"));
+        }
+        if storage > limits.max_compute_workgroup_storage_size as Size {
+            output.problems.push(format!("{shader} uses {} of workgroup memory, but the device allows at most {}", format_bytes(storage), format_bytes(limits.max_compute_workgroup_storage_size as Size)));
+        }
+    }
+
+    // Should match the largest bind group layout in shaders/mod.rs
+    const MAX_STORAGE_BUFFERS: u32 = 5;
+    if limits.max_storage_buffers_per_shader_stage < MAX_STORAGE_BUFFERS {
+        output.problems.push(format!("Some shaders bind {MAX_STORAGE_BUFFERS} storage buffers, but the device allows at most {}", limits.max_storage_buffers_per_shader_stage));
+    }
+
+    return output;
+}
+
+impl Display for Preflight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Estimated gpu memory use: {}", format_bytes(self.total_memory()))?;
+        for buffer in &self.buffers {
+            if buffer.count == 1 {
+                writeln!(f, "  {}: {}", buffer.name, format_bytes(buffer.size))?;
+            } else {
+                writeln!(f, "  {}: {} x {}", buffer.name, buffer.count, format_bytes(buffer.size))?;
+            }
+        }
+        if !self.problems.is_empty() {
+            writeln!(f, "This doesn't fit on the device:")?;
+            for problem in &self.problems {
+                writeln!(f, "  - {problem}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{gpu::{open_device, request_adapter}, input::Config};
+
+    use super::check;
+
+    #[tokio::test]
+    async fn refuses_oversized_configs() {
+        let Some(adapter) = request_adapter().await else {
+            eprintln!("No gpu adapter available, skipping test");
+            return;
+        };
+        let gpu = open_device(adapter).await;
+        let config = |layers: &str| Config::test(&format!(r#"{{ "input_length": 24, "layers": {layers} }}"#));
+
+        let report = check(&gpu, &config("[32, 32, 3]"), 100_000, 10_000);
+        assert!(report.is_ok(), "{report}");
+        assert!(report.total_memory() > 0);
+
+        // The weights of the middle layer are 40 GB
+        let report = check(&gpu, &config("[32, 100000, 100000, 3]"), 1000, 100);
+        assert!(!report.is_ok());
+        assert!(report.problems.iter().any(|p| p.starts_with("layer 2 weights")), "{report}");
+        // The apply shader dispatches a workgroup along the z axis for each node
+        assert!(report.problems.iter().any(|p| p.starts_with("layer 2 apply_backprop_weights")), "{report}");
+    }
+}
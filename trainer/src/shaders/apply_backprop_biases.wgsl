@@ -1,15 +1,22 @@
 /*
  * The backpropagation(_start) shaders have computed arrays of derivatives for the biases of each layer.
- * For each node, the bias derivative needs to be averaged across all iterations. Then, the we sum the
- * averaged derivative with the original bias to compute the new bias
+ * For each node, the bias derivatives are summed across all invocations and added to its gradient.
+ * `apply_gradients.wgsl` averages the gradients and applies them once every part of the data is done.
+ *
+ * If there are a lot of invocations, the sum is split up into chunks. Each workgroup sums a single chunk and
+ * writes it to `partial_sums`, after which `apply_biases_from_chunks` adds up the chunks and adds the result to the gradients.
  */
 
 // The amount of nodes for this layer
 override layer_size: u32;
-// The number of invocations of biases that need to be averaged
+// The number of invocations of biases that need to be summed
 override invocations: u32;
+// The number of invocations summed by a single workgroup
+override chunk_size: u32;
+// The number of chunks the invocations are split into. If this is one, the sums are added to the gradients directly
+override chunks: u32;
 // Turns out naga doesn't like pipeline overridable constants being used like this one is
-// That's why I'm doing an at-home version
+// That's why I'm doing an at-home version. Should be a power of two
 const workers_per_node: u32 = ${workers_per_node};
 
 // The derivatives of the z function for each node in this layer.
@@ -17,50 +24,97 @@ const workers_per_node: u32 = ${workers_per_node};
 // type: array<array<MainType, layer_size>, invocations>
 @group(0) @binding(0)
 var<storage, read> derivZ: array<MainType>;
-// The biases of the network for this layer.
+// The gradients of the biases of this layer.
 // This is the same across all invocations
-// type: array<MainType, output_size>
+// type: array<MainType, layer_size>
 @group(0) @binding(1)
-var<storage, read_write> biases: array<MainType>;
+var<storage, read_write> bias_gradients: array<MainType>;
+// The sum of the derivatives in each chunk. Unused if there's only a single chunk
+// type: array<array<MainType, chunks>, layer_size>
+@group(0) @binding(2)
+var<storage, read_write> partial_sums: array<MainType>;
 
 // Should match constants in `BackpropApplyBiasShaderPipeline` in shaders/mod.rs
 const WORKGROUP_SIZE: u32 = 8;
 
 var<workgroup> tempstorage_sum: array<array<MainType, workers_per_node>, WORKGROUP_SIZE>;
 
+// Adds up the values in `tempstorage_sum` for each node, the result ends up in the first worker's slot.
+// Each step halves the amount of workers that are still adding, so this takes log2(workers_per_node) steps
+fn reduce_workers(local_id: vec3u) {
+    workgroupBarrier();
+    for (var stride = workers_per_node / 2u; stride > 0u; stride /= 2u) {
+        if (local_id.x < stride) {
+            tempstorage_sum[local_id.y][local_id.x] += tempstorage_sum[local_id.y][local_id.x + stride];
+        }
+        workgroupBarrier();
+    }
+}
+
+// Adds the sum of the derivatives to the gradient of the bias
+fn apply(node: u32, sum: MainType) {
+    bias_gradients[node] += sum;
+}
+
 @compute @workgroup_size(workers_per_node, WORKGROUP_SIZE)
 fn apply_biases(
+  @builtin(workgroup_id)
+  group_id: vec3u,
   @builtin(global_invocation_id)
   global_id: vec3u,
   @builtin(local_invocation_id)
   local_id: vec3u
 ) {
     // global_id.y will be the node we're working on
-    if (global_id.y >= layer_size) {
-        return;
-    }
+    let node = global_id.y;
+    // The x axis of the workgroups is used for the chunks
+    let chunk = group_id.x;
 
     // Each node has a number of workers which will sum the derivatives in parallel
     // which worker we are is indicated by local_id.x
-    var iters = invocations / workers_per_node;
-    if (local_id.x == workers_per_node - 1) {
-        iters += invocations % workers_per_node; // One worker needs to handle the remainder
+    // Threads which are out of bounds can't return early, as they need to reach the barriers
+    var sum = KahanSum(0, 0);
+    if (node < layer_size) {
+        let end = min((chunk + 1) * chunk_size, invocations);
+        for (var i = chunk * chunk_size + local_id.x; i < end; i += workers_per_node) {
+            sum = kahan_add(sum, derivZ[node + i * layer_size]);
+        }
     }
-    tempstorage_sum[local_id.y][local_id.x] = MainType(0);
-    for (var i: u32 = 0; i < iters; i++) {
-        let invocation_index = (local_id.x * (invocations / workers_per_node) + i);
-        tempstorage_sum[local_id.y][local_id.x] += derivZ[global_id.y + invocation_index * layer_size];
+    tempstorage_sum[local_id.y][local_id.x] = sum.sum;
+
+    reduce_workers(local_id);
+
+    if (local_id.x == 0 && node < layer_size) {
+        let total = tempstorage_sum[local_id.y][0];
+        if (chunks == 1) {
+            apply(node, total);
+        } else {
+            partial_sums[chunk + node * chunks] = total;
+        }
     }
+}
 
-    // Wait until everyone is done
-    workgroupBarrier();
+// Only used if there are multiple chunks. Sums the chunks and adds the result to the gradients
+@compute @workgroup_size(workers_per_node, WORKGROUP_SIZE)
+fn apply_biases_from_chunks(
+  @builtin(global_invocation_id)
+  global_id: vec3u,
+  @builtin(local_invocation_id)
+  local_id: vec3u
+) {
+    let node = global_id.y;
 
-    // Now we make the first worker sum up everything and write the result
-    if (local_id.x == 0) {
-        var sum: MainType = 0;
-        for (var i: u32 = 0; i < workers_per_node; i++) {
-            sum += tempstorage_sum[local_id.y][i];
+    var sum = KahanSum(0, 0);
+    if (node < layer_size) {
+        for (var i = local_id.x; i < chunks; i += workers_per_node) {
+            sum = kahan_add(sum, partial_sums[i + node * chunks]);
         }
-        biases[global_id.y] -= (learning_rate * sum) / MainType(invocations);
     }
-}
\ No newline at end of file
+    tempstorage_sum[local_id.y][local_id.x] = sum.sum;
+
+    reduce_workers(local_id);
+
+    if (local_id.x == 0 && node < layer_size) {
+        apply(node, tempstorage_sum[local_id.y][0]);
+    }
+}
@@ -1,17 +1,27 @@
 /*
- * The backpropagation(_start) shaders have computed arrays of derivatives for the biases of each layer.
- * For each node, the bias derivative needs to be averaged across all iterations. Then, the we sum the
- * averaged derivative with the original bias to compute the new bias
+ * The backpropagation(_start) shaders have computed arrays of derivatives for each layer.
+ * For each weight, the derivatives are summed across all invocations and added to its gradient.
+ * `apply_gradients.wgsl` averages the gradients and applies them once every part of the data is done.
+ *
+ * The biases are applied by this shader as well, so they don't need a separate pass. A bias works exactly
+ * like a weight whose input activation is always one, so they're treated as an extra node in the previous layer.
+ *
+ * Like with the biases, the sum is split into chunks if there are a lot of invocations.
+ * See `apply_backprop_biases.wgsl`
  */
 
 // The amount of nodes of the previous layer
 override previous_layer_size: u32;
 // The amount of nodes for this layer
 override layer_size: u32;
-// The number of invocations of biases that need to be averaged
+// The number of invocations of derivatives that need to be summed
 override invocations: u32;
+// The number of invocations summed by a single workgroup
+override chunk_size: u32;
+// The number of chunks the invocations are split into. If this is one, the sums are added to the gradients directly
+override chunks: u32;
 // Turns out naga doesn't like pipeline overridable constants being used like this one is
-// That's why I'm doing an at-home version
+// That's why I'm doing an at-home version. Should be a power of two
 const workers_per_node: u32 = ${workers_per_node};
 
 // The activations of the previous layer
@@ -22,10 +32,18 @@ var<storage, read> previous_layer_a: array<MainType>;
 // type: array<array<MainType, layer_size>, invocations>
 @group(0) @binding(1)
 var<storage, read> next_derivZ: array<MainType>;
-// The weights for each connection between the previous and next layer
+// The gradients of the weights for each connection between the previous and next layer
 // type: array<array<MainType, previous_layer_size>, layer_size>
 @group(0) @binding(2)
-var<storage, read_write> weights: array<MainType>;
+var<storage, read_write> weight_gradients: array<MainType>;
+// The gradients of the biases of the layer
+// type: array<MainType, layer_size>
+@group(0) @binding(3)
+var<storage, read_write> bias_gradients: array<MainType>;
+// The sum of the derivatives in each chunk. Unused if there's only a single chunk
+// type: array<array<MainType, chunks>, (previous_layer_size + 1) * layer_size>
+@group(0) @binding(4)
+var<storage, read_write> partial_sums: array<MainType>;
 
 // Should match constants in `BackpropApplyWeightShaderPipeline` in shaders/mod.rs
 const WORKGROUP_SIZE_A: u32 = 8;
@@ -33,49 +51,101 @@ const WORKGROUP_SIZE_B: u32 = 1;
 
 var<workgroup> tempstorage_sum: array<array<array<MainType, workers_per_node>, WORKGROUP_SIZE_A>, WORKGROUP_SIZE_B>;
 
+// Adds up the values in `tempstorage_sum` for each weight, the result ends up in the first worker's slot.
+// Each step halves the amount of workers that are still adding, so this takes log2(workers_per_node) steps
+fn reduce_workers(local_id: vec3u) {
+    workgroupBarrier();
+    for (var stride = workers_per_node / 2u; stride > 0u; stride /= 2u) {
+        if (local_id.x < stride) {
+            tempstorage_sum[local_id.z][local_id.y][local_id.x] += tempstorage_sum[local_id.z][local_id.y][local_id.x + stride];
+        }
+        workgroupBarrier();
+    }
+}
+
+// Where the parameter is stored in `partial_sums`
+fn parameter_index(global_id: vec3u) -> u32 {
+    return global_id.y + global_id.z * (previous_layer_size + 1);
+}
+
+// Adds the sum of the derivatives to the gradient of the weight or bias
+fn apply(global_id: vec3u, sum: MainType) {
+    if (global_id.y == previous_layer_size) {
+        bias_gradients[global_id.z] += sum;
+    } else {
+        weight_gradients[global_id.y + global_id.z * previous_layer_size] += sum;
+    }
+}
+
 @compute @workgroup_size(workers_per_node, WORKGROUP_SIZE_A, WORKGROUP_SIZE_B)
 fn apply_weights(
+  @builtin(workgroup_id)
+  group_id: vec3u,
   @builtin(global_invocation_id)
   global_id: vec3u,
   @builtin(local_invocation_id)
   local_id: vec3u
 ) {
-    // global_id.y will be the node of the previous layer
-    if (global_id.y >= previous_layer_size) {
-        return;
-    }
+    // global_id.y will be the node of the previous layer, or `previous_layer_size` for the bias
     // global_id.z will be the node of the next layer
-    if (global_id.z >= layer_size) {
-        return;
-    }
+    let in_bounds = global_id.y <= previous_layer_size && global_id.z < layer_size;
+    let is_bias = global_id.y == previous_layer_size;
+    // The x axis of the workgroups is used for the chunks
+    let chunk = group_id.x;
 
     // Each node has a number of workers which will sum the derivatives in parallel
-    // which worker we are is indicated by local_id.yx
-    var iters = invocations / workers_per_node;
-    if (local_id.x == workers_per_node - 1) {
-        iters += invocations % workers_per_node; // One worker needs to handle the remainder
+    // which worker we are is indicated by local_id.x
+    // Threads which are out of bounds can't return early, as they need to reach the barriers
+    var sum = KahanSum(0, 0);
+    if (in_bounds) {
+        let end = min((chunk + 1) * chunk_size, invocations);
+        for (var i = chunk * chunk_size + local_id.x; i < end; i += workers_per_node) {
+            // Unlike the bias, which is already computed, we need to do a little extra multiplication
+            // to get the derivative of the weight
+            // Like explained in math.md, derivW is equal to the activation times derivZ
+            var derivative = next_derivZ[global_id.z + i * layer_size];
+            if (!is_bias) {
+                derivative *= previous_layer_a[global_id.y + i * previous_layer_size];
+            }
+            // Add it to the sum so we can average out the iterations.
+            sum = kahan_add(sum, derivative);
+        }
     }
-    
-    tempstorage_sum[local_id.z][local_id.y][local_id.x] = MainType(0);
-    for (var i: u32 = 0; i < iters; i++) {
-        let invocation_index = (local_id.x * (invocations / workers_per_node) + i);
-        // Unlike the bias, which is already computed, we need to do a little extra multiplication
-        // to get the derivative of the weight
-        // Like explained in math.md, derivW is equal to the activation times derivZ
-        let derivative = previous_layer_a[global_id.y + invocation_index * previous_layer_size] * next_derivZ[global_id.z + invocation_index * layer_size];
-        // Add it to the sum so we can average out the iterations.
-        tempstorage_sum[local_id.z][local_id.y][local_id.x] += derivative;
+    tempstorage_sum[local_id.z][local_id.y][local_id.x] = sum.sum;
+
+    reduce_workers(local_id);
+
+    if (local_id.x == 0 && in_bounds) {
+        let total = tempstorage_sum[local_id.z][local_id.y][0];
+        if (chunks == 1) {
+            apply(global_id, total);
+        } else {
+            partial_sums[chunk + parameter_index(global_id) * chunks] = total;
+        }
     }
+}
 
-    // Wait until everyone is done
-    workgroupBarrier();
+// Only used if there are multiple chunks. Sums the chunks and adds the result to the gradients
+@compute @workgroup_size(workers_per_node, WORKGROUP_SIZE_A, WORKGROUP_SIZE_B)
+fn apply_weights_from_chunks(
+  @builtin(global_invocation_id)
+  global_id: vec3u,
+  @builtin(local_invocation_id)
+  local_id: vec3u
+) {
+    let in_bounds = global_id.y <= previous_layer_size && global_id.z < layer_size;
 
-    // Now we make the first worker sum up everything and write the result
-    if (local_id.x == 0) {
-        var sum: MainType = 0;
-        for (var i: u32 = 0; i < workers_per_node; i++) {
-            sum += tempstorage_sum[local_id.z][local_id.y][i];
+    var sum = KahanSum(0, 0);
+    if (in_bounds) {
+        for (var i = local_id.x; i < chunks; i += workers_per_node) {
+            sum = kahan_add(sum, partial_sums[i + parameter_index(global_id) * chunks]);
         }
-        weights[global_id.y + global_id.z * previous_layer_size] -= (learning_rate * sum) / MainType(invocations);
     }
-}
\ No newline at end of file
+    tempstorage_sum[local_id.z][local_id.y][local_id.x] = sum.sum;
+
+    reduce_workers(local_id);
+
+    if (local_id.x == 0 && in_bounds) {
+        apply(global_id, tempstorage_sum[local_id.z][local_id.y][0]);
+    }
+}
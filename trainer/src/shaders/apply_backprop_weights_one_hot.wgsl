@@ -0,0 +1,55 @@
+/*
+ * Like `apply_backprop_weights`, but for a layer whose input is one-hot encoded.
+ * The derivative of a weight is the activation of its input node times derivZ. The activation
+ * is either 0 or 1, so only the invocations where the input node is active contribute to the sum.
+ * These are precomputed for each input node, so each weight only has to visit those invocations.
+ */
+
+// The amount of nodes in the input layer
+override input_size: u32;
+// The amount of nodes for this layer
+override layer_size: u32;
+
+// For each input node, the start of its range in `column_invocations`.
+// The range of the last node ends at the extra entry at the end.
+// type: array<u32, input_size + 1>
+@group(0) @binding(0)
+var<storage, read> column_offsets: array<u32>;
+// For each input node, the invocations in which it is active
+// type: array<u32>
+@group(0) @binding(1)
+var<storage, read> column_invocations: array<u32>;
+// The derivatives of the z function for each node in the layer that the weights connect to.
+// type: array<array<MainType, layer_size>, invocations>
+@group(0) @binding(2)
+var<storage, read> next_derivZ: array<MainType>;
+// The gradients of the weights for each connection between the input and this layer
+// type: array<array<MainType, input_size>, layer_size>
+@group(0) @binding(3)
+var<storage, read_write> weight_gradients: array<MainType>;
+
+// Should match constants in `ApplyOneHotWeightShaderPipeline` in shaders/mod.rs
+const WORKGROUP_SIZE_A: u32 = 8;
+const WORKGROUP_SIZE_B: u32 = 8;
+
+@compute @workgroup_size(WORKGROUP_SIZE_A, WORKGROUP_SIZE_B)
+fn apply_weights_one_hot(
+  @builtin(global_invocation_id)
+  global_id: vec3u
+) {
+    // global_id.x will be the node of the input layer
+    if (global_id.x >= input_size) {
+        return;
+    }
+    // global_id.y will be the node of this layer
+    if (global_id.y >= layer_size) {
+        return;
+    }
+
+    var sum = KahanSum(0, 0);
+    for (var i = column_offsets[global_id.x]; i < column_offsets[global_id.x + 1]; i++) {
+        let invocation_index = column_invocations[i];
+        sum = kahan_add(sum, next_derivZ[global_id.y + invocation_index * layer_size]);
+    }
+    weight_gradients[global_id.x + global_id.y * input_size] += sum.sum;
+}
@@ -0,0 +1,40 @@
+/*
+ * The apply shaders add the derivatives of each part of the data to a gradient buffer, instead of
+ * changing the parameters directly. Once every part is done, this shader applies the averaged gradient
+ * to the parameters and resets the gradient for the next iteration. Data that fits in a single part goes
+ * through here as well, so the result doesn't depend on how the data was split.
+ */
+
+// The amount of parameters in the buffer
+override size: u32;
+// The total number of invocations over all parts of the data
+override invocations: u32;
+
+// The summed derivatives of each parameter
+// type: array<MainType, size>
+@group(0) @binding(0)
+var<storage, read_write> gradients: array<MainType>;
+// The parameters, these are either the weights or the biases of a layer
+// type: array<MainType, size>
+@group(0) @binding(1)
+var<storage, read_write> parameters: array<MainType>;
+
+// Should match constant in `ApplyGradientsShaderPipeline` in shaders/mod.rs
+const WORKGROUP_SIZE: u32 = 64;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn apply_gradients(
+  @builtin(global_invocation_id)
+  global_id: vec3u,
+  @builtin(num_workgroups)
+  num_workgroups: vec3u
+) {
+    // There might be more parameters than workgroups fit in a single dimension, so the y axis is used as well
+    let i = global_id.x + global_id.y * num_workgroups.x * WORKGROUP_SIZE;
+    if (i >= size) {
+        return;
+    }
+
+    parameters[i] -= (learning_rate * gradients[i]) / MainType(invocations);
+    gradients[i] = MainType(0);
+}
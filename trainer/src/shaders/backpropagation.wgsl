@@ -9,6 +9,9 @@ override next_layer_size: u32;
 override layer_size: u32;
 // The number of invocations that this shader will do at once
 override invocations: u32;
+// Naga doesn't like pipeline overridable constants being used for array sizes
+// That's why I'm doing an at-home version. The value is picked by `tile_size` in shaders/mod.rs
+const TILE_SIZE: u32 = ${tile_size};
 
 // The weights connecting this layer to the next layer
 // type: array<array<MainType, layer_size>, next_layer_size>
@@ -30,41 +33,60 @@ var<storage, read> next_layer_derivZ: array<MainType>;
 @group(0) @binding(3)
 var<storage, read_write> derivZ: array<MainType>;
 
+// Like `compute_forwards`, the sum in this shader is a matrix multiplication.
+// It's tiled in the same way, see that shader for an explanation.
+// type: array<array<MainType, TILE_SIZE (next layer node)>, TILE_SIZE (invocation)>
+var<workgroup> tile_next_derivZ: array<array<MainType, TILE_SIZE>, TILE_SIZE>;
+// type: array<array<MainType, TILE_SIZE (node)>, TILE_SIZE (next layer node)>
+var<workgroup> tile_weights: array<array<MainType, TILE_SIZE>, TILE_SIZE>;
+
 // This shader is used for all backpropagation steps except the first.
 // It derives (dC_0/dz) from the (dC_0/dz) values of the previous step
 // See math.md
-@compute @workgroup_size(STD_WORKGROUP_SIZE.x, STD_WORKGROUP_SIZE.y, STD_WORKGROUP_SIZE.z)
+@compute @workgroup_size(TILE_SIZE, TILE_SIZE)
 fn backprop_from_layer(
-  @builtin(global_invocation_id)
-  global_id: vec3u
+  @builtin(workgroup_id)
+  group_id: vec3u,
+  @builtin(local_invocation_id)
+  local_id: vec3u
 ) {
-    if (is_out_of_bounds(global_id)) {
-        return;
-    }
+    // The x axis of the workgroups is used for the invocations, the y axis for the nodes in this layer
+    let invocation = group_id.x * TILE_SIZE + local_id.y;
+    // the `i` in math.md matches `node` here
+    let node = group_id.y * TILE_SIZE + local_id.x;
 
     var derivA: MainType = 0;
-    
-    // Using j as variable here to match the sum in the math.md equation
-    // the `i` in math.md matches `global_id.y` here
-    for (var j: u32 = 0; j < next_layer_size; j++) {
-        let weight = next_layer_weights[global_id.y + j * layer_size];
-        let next_derivZ = next_layer_derivZ[j + global_id.x * next_layer_size];
-        derivA += weight * next_derivZ;
-    }
 
-    let i = global_id.y + global_id.x * layer_size;
-    derivZ[i] = dActivation(layer_z[i]) * derivA;
-}
+    // Threads which are out of bounds can't return early, they still need to help load the tiles
+    for (var tile_start: u32 = 0; tile_start < next_layer_size; tile_start += TILE_SIZE) {
+        // Anything out of bounds is set to zero, so it doesn't contribute to the sum
+        let next_node = tile_start + local_id.x;
+        if (invocation < invocations && next_node < next_layer_size) {
+            tile_next_derivZ[local_id.y][local_id.x] = next_layer_derivZ[next_node + invocation * next_layer_size];
+        } else {
+            tile_next_derivZ[local_id.y][local_id.x] = MainType(0);
+        }
+        let weight_next_node = tile_start + local_id.y;
+        if (node < layer_size && weight_next_node < next_layer_size) {
+            tile_weights[local_id.y][local_id.x] = next_layer_weights[node + weight_next_node * layer_size];
+        } else {
+            tile_weights[local_id.y][local_id.x] = MainType(0);
+        }
 
-fn is_out_of_bounds(global_id: vec3u) -> bool {
-    // global_id.x represents which invocation we're in
-    // (this shader is meant to run the same neural network on multiple inputs at once)
-    if (global_id.x >= invocations) {
-        return true;
+        // Wait until the tiles are loaded
+        workgroupBarrier();
+
+        // Using j as variable here to match the sum in the math.md equation
+        for (var j: u32 = 0; j < TILE_SIZE; j++) {
+            derivA += tile_weights[j][local_id.x] * tile_next_derivZ[local_id.y][j];
+        }
+
+        // Wait until everyone is done with the tiles before loading the next ones
+        workgroupBarrier();
     }
-    // global_id.y is the index of the node in the output layer which we want to compute
-    if (global_id.y >= layer_size) {
-        return true;
+
+    if (invocation < invocations && node < layer_size) {
+        let i = node + invocation * layer_size;
+        derivZ[i] = dActivation(layer_z[i]) * derivA;
     }
-    return false;
-}
\ No newline at end of file
+}
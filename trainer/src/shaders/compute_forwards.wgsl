@@ -4,6 +4,9 @@ override input_size: u32;
 override output_size: u32;
 // The number of invocations that this shader will do at once
 override invocations: u32;
+// Naga doesn't like pipeline overridable constants being used for array sizes
+// That's why I'm doing an at-home version. The value is picked by `tile_size` in shaders/mod.rs
+const TILE_SIZE: u32 = ${tile_size};
 
 // The weights for each connection between the input and output layer
 // type: array<array<MainType, input_size>, output_size>
@@ -28,31 +31,63 @@ var<storage, read_write> output_z: array<MainType>;
 @group(0) @binding(4)
 var<storage, read_write> output_a: array<MainType>;
 
-@compute @workgroup_size(STD_WORKGROUP_SIZE.x, STD_WORKGROUP_SIZE.y, STD_WORKGROUP_SIZE.z)
+// Computing the z values is a matrix multiplication of the input activations with the weights.
+// Each workgroup computes a block of TILE_SIZE invocations by TILE_SIZE output nodes. It walks over
+// the input nodes one tile at a time, where the threads load the tile together into workgroup memory.
+// That way each weight and activation is read from storage once per workgroup instead of once per thread.
+// type: array<array<MainType, TILE_SIZE (input node)>, TILE_SIZE (invocation)>
+var<workgroup> tile_a: array<array<MainType, TILE_SIZE>, TILE_SIZE>;
+// type: array<array<MainType, TILE_SIZE (input node)>, TILE_SIZE (output node)>
+var<workgroup> tile_weights: array<array<MainType, TILE_SIZE>, TILE_SIZE>;
+
+@compute @workgroup_size(TILE_SIZE, TILE_SIZE)
 fn compute_forwards(
-  @builtin(global_invocation_id)
-  global_id: vec3u
+  @builtin(workgroup_id)
+  group_id: vec3u,
+  @builtin(local_invocation_id)
+  local_id: vec3u
 ) {
-    // global_id.x represents which invocation we're in
-    // (this shader is meant to run the same neural network on multiple inputs at once)
-    if (global_id.x >= invocations) {
-        return;
-    }
-    // global_id.y is the index of the node in the output layer which we want to compute
-    if (global_id.y >= output_size) {
-        return;
-    }
+    // The x axis of the workgroups is used for the invocations, the y axis for the nodes in the output layer.
+    // Inside of the workgroup, local_id.x is the fastest changing index, so it's used for whichever index
+    // makes neighbouring threads access neighbouring memory.
+    let invocation = group_id.x * TILE_SIZE + local_id.y;
+    let node = group_id.y * TILE_SIZE + local_id.x;
+    // The node of the output layer for which this thread loads weights
+    let weight_node = group_id.y * TILE_SIZE + local_id.y;
 
     var output: MainType = 0;
-    
-    for (var i: u32 = 0; i < input_size; i++) {
-        // The weight from the node in the previous layer to the node we're calculating
-        let weight = weights[i + global_id.y * input_size];
-        let input_activation = input_a[i + global_id.x * input_size];
-        output += input_activation * weight;
+
+    // Threads which are out of bounds can't return early, they still need to help load the tiles
+    for (var tile_start: u32 = 0; tile_start < input_size; tile_start += TILE_SIZE) {
+        let input_node = tile_start + local_id.x;
+
+        // Anything out of bounds is set to zero, so it doesn't contribute to the sum
+        if (invocation < invocations && input_node < input_size) {
+            tile_a[local_id.y][local_id.x] = input_a[input_node + invocation * input_size];
+        } else {
+            tile_a[local_id.y][local_id.x] = MainType(0);
+        }
+        if (weight_node < output_size && input_node < input_size) {
+            tile_weights[local_id.y][local_id.x] = weights[input_node + weight_node * input_size];
+        } else {
+            tile_weights[local_id.y][local_id.x] = MainType(0);
+        }
+
+        // Wait until the tiles are loaded
+        workgroupBarrier();
+
+        for (var i: u32 = 0; i < TILE_SIZE; i++) {
+            output += tile_a[local_id.y][i] * tile_weights[local_id.x][i];
+        }
+
+        // Wait until everyone is done with the tiles before loading the next ones
+        workgroupBarrier();
     }
-    output += biases[global_id.y];
 
-    output_z[global_id.y + global_id.x * output_size] = output;
-    output_a[global_id.y + global_id.x * output_size] = activation(output);
-}
\ No newline at end of file
+    if (invocation < invocations && node < output_size) {
+        output += biases[node];
+
+        output_z[node + invocation * output_size] = output;
+        output_a[node + invocation * output_size] = activation(output);
+    }
+}
@@ -0,0 +1,68 @@
+// The amount of nodes in the input layer
+override input_size: u32;
+// The amount of nodes in the output layer
+override output_size: u32;
+// The number of invocations that this shader will do at once
+override invocations: u32;
+// The maximum amount of input nodes which can be active for each invocation
+override active_inputs: u32;
+
+// Used to pad out the active inputs, should match `NO_INPUT` in string.rs
+const NO_INPUT: u32 = 0xffffffffu;
+
+// The weights for each connection between the input and output layer
+// type: array<array<MainType, input_size>, output_size>
+@group(0) @binding(0)
+var<storage, read> weights: array<MainType>;
+// The biases of the network for this layer.
+// This is the same across all invocations
+// type: array<MainType, output_size>
+@group(0) @binding(1)
+var<storage, read> biases: array<MainType>;
+
+// The input is one-hot encoded, so instead of storing all activations
+// we only store the indices of the nodes which are 1. The rest are 0.
+// type: array<array<u32, active_inputs>, invocations>
+@group(0) @binding(2)
+var<storage, read> input_active: array<u32>;
+// The output "z" values for this layer. See math.md
+// type: array<array<MainType, output_size>, invocations>
+@group(0) @binding(3)
+var<storage, read_write> output_z: array<MainType>;
+// The output "a" values for this layer. See math.md
+// type: array<array<MainType, output_size>, invocations>
+@group(0) @binding(4)
+var<storage, read_write> output_a: array<MainType>;
+
+// Same as `compute_forwards`, but only looks at the input nodes which are active
+@compute @workgroup_size(STD_WORKGROUP_SIZE.x, STD_WORKGROUP_SIZE.y, STD_WORKGROUP_SIZE.z)
+fn compute_forwards_one_hot(
+  @builtin(global_invocation_id)
+  global_id: vec3u
+) {
+    // global_id.x represents which invocation we're in
+    // (this shader is meant to run the same neural network on multiple inputs at once)
+    if (global_id.x >= invocations) {
+        return;
+    }
+    // global_id.y is the index of the node in the output layer which we want to compute
+    if (global_id.y >= output_size) {
+        return;
+    }
+
+    var output: MainType = 0;
+
+    for (var i: u32 = 0; i < active_inputs; i++) {
+        let input = input_active[i + global_id.x * active_inputs];
+        // The padding is always at the end
+        if (input == NO_INPUT) {
+            break;
+        }
+        // The activation of the input is 1, so the weight can be added directly
+        output += weights[input + global_id.y * input_size];
+    }
+    output += biases[global_id.y];
+
+    output_z[global_id.y + global_id.x * output_size] = output;
+    output_a[global_id.y + global_id.x * output_size] = activation(output);
+}
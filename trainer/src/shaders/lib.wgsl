@@ -1,3 +1,4 @@
+// Should match `MainType` in layer/mod.rs
 alias MainType = f32;
 
 // Should match the constants in shaders/mod.rs
@@ -21,4 +22,18 @@ fn dActivation(x: MainType) -> MainType {
     } else {
         return 0.01;
     }
-}
\ No newline at end of file
+}
+
+// A running sum which keeps track of its own rounding error (Kahan summation).
+// When summing the derivatives of many invocations, the sum quickly becomes much larger than
+// each derivative, and plain f32 addition would round away most of the small ones
+struct KahanSum {
+    sum: MainType,
+    compensation: MainType,
+}
+
+fn kahan_add(acc: KahanSum, value: MainType) -> KahanSum {
+    let y = value - acc.compensation;
+    let t = acc.sum + y;
+    return KahanSum(t, (t - acc.sum) - y);
+}
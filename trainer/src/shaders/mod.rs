@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 
-use wgpu::{BindGroupLayout, ComputePass, ComputePipeline, Device, PipelineCompilationOptions, PipelineLayoutDescriptor, ShaderModule};
+use wgpu::{BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, ComputePass, ComputePipeline, Device, Limits, PipelineCompilationOptions, PipelineLayoutDescriptor};
 
 use map_macro::hash_map;
 
-use crate::{gpu::GpuDeviceData, input::{Config, LayerConfig}, misc::{bind_group_layout, ceil_div, floor_div, IterPow2}};
+use crate::{gpu::GpuDeviceData, layer::{MainType, Size}, misc::{bind_group_layout, ceil_div, size_of, IterPow2}};
 
 macro_rules! include_shader_str {
     ($($token:tt)*) => {
@@ -12,16 +12,22 @@ macro_rules! include_shader_str {
     };
 }
 
-macro_rules! include_shader {
-    ($($token:tt)*) => {
-        wgpu::ShaderModuleDescriptor {
-            label: Some($($token)*),
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(include_shader_str!($($token)*)))
-        }
-    };
+/// Fills in any of our own "pipeline overridable constants at home" in the source
+fn wgsl_source(label: &'static str, source: &str, replacements: &[(&str, String)]) -> WgslSource {
+    let mut source = source.to_owned();
+    for (key, value) in replacements {
+        source = source.replace(key, value);
+    }
+    WgslSource { label, source }
 }
 
+/// Compiles a pipeline from its own shader module. Some backends (GL in wgpu 0.20) cache compiled programs
+/// per module and entry point without looking at the overridable constants, so pipelines must never share a module
 pub fn create_pipeline(device: &Device, component: &ShaderComponent, name: &str, entrypoint: &str, constants: HashMap<String, f64>) -> ComputePipeline {
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(component.1.label),
+        source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(&component.1.source))
+    });
     device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
         label: Some(name),
         layout: Some(&device.create_pipeline_layout(
@@ -31,7 +37,7 @@ pub fn create_pipeline(device: &Device, component: &ShaderComponent, name: &str,
                 push_constant_ranges: &[]
             }
         )),
-        module: &component.1,
+        module: &module,
         entry_point: entrypoint,
         compilation_options: PipelineCompilationOptions {
             constants: &constants,
@@ -40,23 +46,41 @@ pub fn create_pipeline(device: &Device, component: &ShaderComponent, name: &str,
     })
 }
 
-pub struct ShaderComponent(pub BindGroupLayout, ShaderModule);
+/// The WGSL code of a shader, with our own constants already filled in
+struct WgslSource {
+    label: &'static str,
+    source: String,
+}
+
+pub struct ShaderComponent(pub BindGroupLayout, WgslSource);
 
 pub struct ShaderComponents {
     pub compute_forwards: ShaderComponent,
     pub backpropagation_start: ShaderComponent,
     pub backpropagation: ShaderComponent,
+    pub compute_forwards_one_hot: ShaderComponent,
+    pub apply_backprop_weights_one_hot: ShaderComponent,
+    pub apply_gradients: ShaderComponent,
+    /// The width and height of the tiles used by `compute_forwards` and `backpropagation`
+    pub tile_size: u32,
 }
 
-pub struct ShaderSet {
-    pub compute_forwards: StandardShaderPipeline,
-    /// Will be either `backpropagation_start` or `backpropagation` depending on if this is the final layer or not
-    pub backpropagation: StandardShaderPipeline,
-    pub apply_backprop_biases: BackpropApplyBiasShaderPipeline,
-    pub apply_backprop_weights: BackpropApplyWeightShaderPipeline,
+fn compute_forwards(device: &Device, tile_size: u32) -> ShaderComponent {
+    let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
+        { binding: 0, read_only: true },
+        { binding: 1, read_only: true },
+        { binding: 2, read_only: true },
+        { binding: 3, read_only: false },
+        { binding: 4, read_only: false },
+    ]);
+
+    // Pipeline overridable constants at home
+    let source = wgsl_source("compute_forwards.wgsl", include_shader_str!("compute_forwards.wgsl"), &[("${tile_size}", tile_size.to_string())]);
+
+    ShaderComponent(bind_group_layout, source)
 }
 
-fn compute_forwards(device: &Device) -> ShaderComponent {
+fn compute_forwards_one_hot(device: &Device) -> ShaderComponent {
     let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
         { binding: 0, read_only: true },
         { binding: 1, read_only: true },
@@ -65,9 +89,9 @@ fn compute_forwards(device: &Device) -> ShaderComponent {
         { binding: 4, read_only: false },
     ]);
 
-    let module = device.create_shader_module(include_shader!("compute_forwards.wgsl"));
+    let source = wgsl_source("compute_forwards_one_hot.wgsl", include_shader_str!("compute_forwards_one_hot.wgsl"), &[]);
 
-    ShaderComponent(bind_group_layout, module)
+    ShaderComponent(bind_group_layout, source)
 }
 
 fn backpropation_start(device: &Device) -> ShaderComponent {
@@ -78,12 +102,12 @@ fn backpropation_start(device: &Device) -> ShaderComponent {
         { binding: 3, read_only: false },
     ]);
 
-    let module = device.create_shader_module(include_shader!("backpropagation_start.wgsl"));
+    let source = wgsl_source("backpropagation_start.wgsl", include_shader_str!("backpropagation_start.wgsl"), &[]);
 
-    ShaderComponent(bind_group_layout, module)
+    ShaderComponent(bind_group_layout, source)
 }
 
-fn backpropation(device: &Device) -> ShaderComponent {
+fn backpropation(device: &Device, tile_size: u32) -> ShaderComponent {
     let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
         { binding: 0, read_only: true },
         { binding: 1, read_only: true },
@@ -91,30 +115,23 @@ fn backpropation(device: &Device) -> ShaderComponent {
         { binding: 3, read_only: false },
     ]);
 
-    let module = device.create_shader_module(include_shader!("backpropagation.wgsl"));
+    // Pipeline overridable constants at home
+    let source = wgsl_source("backpropagation.wgsl", include_shader_str!("backpropagation.wgsl"), &[("${tile_size}", tile_size.to_string())]);
 
-    ShaderComponent(bind_group_layout, module)
+    ShaderComponent(bind_group_layout, source)
 }
 
 fn apply_backprop_biases(device: &Device, workers_per_node: usize) -> ShaderComponent {
     let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
         { binding: 0, read_only: true },
         { binding: 1, read_only: false },
+        { binding: 2, read_only: false },
     ]);
 
     // Pipeline overridable constants at home
-    let module = device.create_shader_module(
-        wgpu::ShaderModuleDescriptor {
-            label: Some("apply_backprop_biases.wgsl"),
-            source: wgpu::ShaderSource::Wgsl(
-                std::borrow::Cow::Owned(
-                    include_shader_str!("apply_backprop_biases.wgsl").replace("${workers_per_node}", &workers_per_node.to_string())
-                )
-            )
-        }
-    );
+    let source = wgsl_source("apply_backprop_biases.wgsl", include_shader_str!("apply_backprop_biases.wgsl"), &[("${workers_per_node}", workers_per_node.to_string())]);
 
-    ShaderComponent(bind_group_layout, module)
+    ShaderComponent(bind_group_layout, source)
 }
 
 fn apply_backprop_weights(device: &Device, workers_per_node: usize) -> ShaderComponent {
@@ -122,147 +139,366 @@ fn apply_backprop_weights(device: &Device, workers_per_node: usize) -> ShaderCom
         { binding: 0, read_only: true },
         { binding: 1, read_only: true },
         { binding: 2, read_only: false },
+        { binding: 3, read_only: false },
+        { binding: 4, read_only: false },
     ]);
 
     // Pipeline overridable constants at home
-    let module = device.create_shader_module(
-        wgpu::ShaderModuleDescriptor {
-            label: Some("apply_backprop_weights.wgsl"),
-            source: wgpu::ShaderSource::Wgsl(
-                std::borrow::Cow::Owned(
-                    include_shader_str!("apply_backprop_weights.wgsl").replace("${workers_per_node}", &workers_per_node.to_string())
-                )
-            )
-        }
-    );
+    let source = wgsl_source("apply_backprop_weights.wgsl", include_shader_str!("apply_backprop_weights.wgsl"), &[("${workers_per_node}", workers_per_node.to_string())]);
+
+    ShaderComponent(bind_group_layout, source)
+}
+
+fn apply_backprop_weights_one_hot(device: &Device) -> ShaderComponent {
+    let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
+        { binding: 0, read_only: true },
+        { binding: 1, read_only: true },
+        { binding: 2, read_only: true },
+        { binding: 3, read_only: false },
+    ]);
+
+    let source = wgsl_source("apply_backprop_weights_one_hot.wgsl", include_shader_str!("apply_backprop_weights_one_hot.wgsl"), &[]);
+
+    ShaderComponent(bind_group_layout, source)
+}
+
+fn apply_gradients(device: &Device) -> ShaderComponent {
+    let bind_group_layout = device.create_bind_group_layout(&bind_group_layout![
+        { binding: 0, read_only: false },
+        { binding: 1, read_only: false },
+    ]);
 
-    ShaderComponent(bind_group_layout, module)
+    let source = wgsl_source("apply_gradients.wgsl", include_shader_str!("apply_gradients.wgsl"), &[]);
+
+    ShaderComponent(bind_group_layout, source)
 }
 
 impl ShaderComponents {
     pub fn init(device: &Device) -> Self {
+        let tile_size = tile_size(&device.limits());
         Self {
-            compute_forwards: compute_forwards(device),
+            compute_forwards: compute_forwards(device, tile_size),
             backpropagation_start: backpropation_start(device),
-            backpropagation: backpropation(device),
+            backpropagation: backpropation(device, tile_size),
+            compute_forwards_one_hot: compute_forwards_one_hot(device),
+            apply_backprop_weights_one_hot: apply_backprop_weights_one_hot(device),
+            apply_gradients: apply_gradients(device),
+            tile_size,
         }
     }
 }
 
-impl ShaderSet {
-    /// Compiles a set of shaders, the shaders are designed to
-    /// evaluate multiple neural networks at once
-    pub fn compile(gpu: &GpuDeviceData, config: &Config, invocations: usize) -> Vec<Self> {
-        // Estimate the number of computations the shader does for each possible option and take the minimum
-        // (we consider only powers of two as options)
-        let workers_per_node = IterPow2::range(..=invocations)
-            .filter(|n| *n <= gpu.device.limits().max_compute_workgroup_size_x as usize)
-            .min_by_key(|n| (floor_div(invocations, *n) + (invocations % n) + n)).unwrap();
+/// Picks the largest tile size for the tiled matrix multiplications that the device can handle.
+/// A workgroup has one thread per element of the tile, and stores two tiles in workgroup memory.
+pub fn tile_size(limits: &Limits) -> u32 {
+    [16, 8, 4, 2, 1].into_iter()
+        .find(|t| {
+            t * t <= limits.max_compute_invocations_per_workgroup
+                && *t <= limits.max_compute_workgroup_size_x
+                && *t <= limits.max_compute_workgroup_size_y
+                && 2 * t * t * size_of::<MainType>() as u32 <= limits.max_compute_workgroup_storage_size
+        })
+        .unwrap_or(1)
+}
+
+// The apply shaders sum this many invocations in a single workgroup, anything more gets split into chunks
+const MIN_CHUNK_SIZE: usize = 4096;
+
+/// How the apply shaders split up the sum over all invocations
+struct Reduction {
+    /// The amount of threads that work on the sum of a single parameter. Always a power of two
+    workers_per_node: usize,
+    /// The amount of invocations summed by a single workgroup
+    chunk_size: usize,
+    /// The amount of workgroups each parameter is split over
+    chunks: usize,
+}
 
-        // These shaders are a little special, they have some hacks around the lack of good pipeline overridable constant support
-        let apply_backprop_biases = apply_backprop_biases(&gpu.device, workers_per_node);
-        let apply_backprop_weights = apply_backprop_weights(&gpu.device, workers_per_node);
+/// Decides how to sum the derivatives of `parameters` parameters over all invocations.
+/// `nodes_per_workgroup` is the amount of parameters that a single workgroup handles.
+fn reduction(gpu: &GpuDeviceData, invocations: usize, parameters: Size, nodes_per_workgroup: usize) -> Reduction {
+    let limits = gpu.device.limits();
+
+    // Every chunk needs its own slot in the buffer of partial sums
+    let max_chunks = (limits.max_storage_buffer_binding_size as u64 / (parameters * size_of::<MainType>())) as usize;
+    let chunks = ceil_div(invocations as u32, MIN_CHUNK_SIZE as u64)
+        .min(limits.max_compute_workgroups_per_dimension)
+        .min(max_chunks as u32)
+        .max(1) as usize;
+    let chunk_size = ceil_div(invocations as u32, chunks as u64) as usize;
+
+    // Estimate the number of computations the apply shaders do for each possible option and take the minimum
+    // (we consider only powers of two as options, the shaders rely on that for their reduction)
+    let workers_per_node = IterPow2::range(..=chunk_size.max(chunks).next_power_of_two())
+        .filter(|n| *n <= limits.max_compute_workgroup_size_x as usize)
+        .filter(|n| n * nodes_per_workgroup <= limits.max_compute_invocations_per_workgroup as usize)
+        .filter(|n| (n * nodes_per_workgroup) as u64 * size_of::<MainType>() <= limits.max_compute_workgroup_storage_size as u64)
+        .min_by_key(|n| chunk_size.div_ceil(*n) + n.ilog2() as usize).unwrap();
+
+    Reduction {
+        workers_per_node,
+        chunk_size,
+        chunks,
+    }
+}
 
-        let mut output = Vec::new();
+/// Size in bytes of the buffer the apply shaders store the sum of each chunk in
+fn partial_sums_size(reduction: &Reduction, parameters: Size) -> Size {
+    // Empty buffers can't be bound
+    if reduction.chunks == 1 { size_of::<MainType>() } else { parameters * reduction.chunks as u64 * size_of::<MainType>() }
+}
 
-        for (i, layer) in config.layers().into_iter().enumerate() {
-            output.push(ShaderSet::compile_single(gpu, layer, invocations, i == config.layers().len() - 1, (&apply_backprop_biases, &apply_backprop_weights)));
+/// A buffer for the apply shaders to store the sum of each chunk in
+fn partial_sums_buffer(gpu: &GpuDeviceData, reduction: &Reduction, parameters: Size) -> Buffer {
+    gpu.device.create_buffer(&BufferDescriptor {
+        label: Some("partial sums"),
+        size: partial_sums_size(reduction, parameters),
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+// Should match constant in `apply_backprop_biases.wgsl`
+const APPLY_BIASES_WORKGROUP_SIZE: usize = 8;
+// Should match constants in `apply_backprop_weights.wgsl` (WORKGROUP_SIZE_A * WORKGROUP_SIZE_B)
+const APPLY_WEIGHTS_WORKGROUP_SIZE: usize = 8;
+
+/// The amount of workgroups `compile_apply_backprop_biases` splits each sum over, and the size of its buffer of partial sums
+pub fn apply_backprop_biases_chunks(gpu: &GpuDeviceData, layer_size: Size, invocations: usize) -> (usize, Size) {
+    let reduction = reduction(gpu, invocations, layer_size, APPLY_BIASES_WORKGROUP_SIZE);
+    (reduction.chunks, partial_sums_size(&reduction, layer_size))
+}
+
+/// The amount of workgroups `compile_apply_backprop_weights` splits each sum over, and the size of its buffer of partial sums
+pub fn apply_backprop_weights_chunks(gpu: &GpuDeviceData, previous_layer_size: Size, layer_size: Size, invocations: usize) -> (usize, Size) {
+    let parameters = (previous_layer_size + 1) * layer_size;
+    let reduction = reduction(gpu, invocations, parameters, APPLY_WEIGHTS_WORKGROUP_SIZE);
+    (reduction.chunks, partial_sums_size(&reduction, parameters))
+}
+
+// The shaders below are designed to evaluate the neural network on multiple inputs at once
+
+pub fn compile_compute_forwards(gpu: &GpuDeviceData, input_size: Size, output_size: Size, invocations: usize) -> TiledShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.compute_forwards,
+        "Compute Forwards",
+        "compute_forwards",
+        hash_map! {
+            "input_size".to_owned() => input_size as f64,
+            "output_size".to_owned() => output_size as f64,
+            "invocations".to_owned() => invocations as f64,
         }
+    );
 
-        return output;
+    TiledShaderPipeline {
+        pipeline,
+        invocations: invocations as u32,
+        layer_size: output_size as u32,
+        tile_size: gpu.shader_components.tile_size,
     }
+}
 
-    fn compile_single(gpu: &GpuDeviceData, layer: LayerConfig, invocations: usize, final_layer: bool, apply_backprops: (&ShaderComponent, &ShaderComponent)) -> Self {
-        let device = &gpu.device;
-        let components = &gpu.shader_components;
-
-        let compute_forwards = 
-        create_pipeline(
-            device,
-            &components.compute_forwards,
-            "Compute Forwards",
-            "compute_forwards",
-            hash_map! {
-                "input_size".to_owned() => layer.previous_size as f64,
-                "output_size".to_owned() => layer.size as f64,
-                "invocations".to_owned() => invocations as f64,
-            }
-        );
+pub fn compile_compute_forwards_one_hot(gpu: &GpuDeviceData, input_size: Size, output_size: Size, active_inputs: Size, invocations: usize) -> StandardShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.compute_forwards_one_hot,
+        "Compute Forwards (one-hot)",
+        "compute_forwards_one_hot",
+        hash_map! {
+            "input_size".to_owned() => input_size as f64,
+            "output_size".to_owned() => output_size as f64,
+            "active_inputs".to_owned() => active_inputs as f64,
+            "invocations".to_owned() => invocations as f64,
+        }
+    );
 
-        let backpropagation = if final_layer {
-            create_pipeline(
-                device,
-                &components.backpropagation_start,
-                "Backpropagation First Step",
-                "backprop_from_cost",
-                hash_map! {
-                    "layer_size".to_owned() => layer.size as f64,
-                    "invocations".to_owned() => invocations as f64,
-                }
-            )
-        } else {
-            create_pipeline(
-                device,
-                &components.backpropagation,
-                "Backpropagation",
-                "backprop_from_layer",
-                hash_map! {
-                    "layer_size".to_owned() => layer.size as f64,
-                    "next_layer_size".to_owned() => layer.next_size.unwrap() as f64,
-                    "invocations".to_owned() => invocations as f64,
-                }
-            )
-        };
-
-        let apply_backprop_biases = create_pipeline(
-            device,
-            &apply_backprops.0,
-            "Backprop apply biases",
-            "apply_biases",
-            hash_map! {
-                "layer_size".to_owned() => layer.size as f64,
-                "invocations".to_owned() => invocations as f64,
-            }
-        );
+    StandardShaderPipeline {
+        pipeline,
+        invocations: invocations as u32,
+        layer_size: output_size as u32,
+    }
+}
 
-        let apply_backprop_weights = create_pipeline(
-            device,
-            &apply_backprops.1,
-            "Backprop apply weights",
-            "apply_weights",
-            hash_map! {
-                "previous_layer_size".to_owned() => layer.previous_size as f64,
-                "layer_size".to_owned() => layer.size as f64,
-                "invocations".to_owned() => invocations as f64,
-            }
-        );
+pub fn compile_backpropagation_start(gpu: &GpuDeviceData, layer_size: Size, invocations: usize) -> StandardShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.backpropagation_start,
+        "Backpropagation First Step",
+        "backprop_from_cost",
+        hash_map! {
+            "layer_size".to_owned() => layer_size as f64,
+            "invocations".to_owned() => invocations as f64,
+        }
+    );
 
-        Self {
-            compute_forwards: StandardShaderPipeline {
-                pipeline: compute_forwards,
-                invocations: invocations as u32,
-                layer_size: layer.size as u32,
-            },
-            backpropagation: StandardShaderPipeline {
-                pipeline: backpropagation,
-                invocations: invocations as u32,
-                layer_size: layer.size as u32,
-            },
-            apply_backprop_biases: BackpropApplyBiasShaderPipeline {
-                pipeline: apply_backprop_biases,
-                layer_size: layer.size as u32,
-            },
-            apply_backprop_weights: BackpropApplyWeightShaderPipeline {
-                pipeline: apply_backprop_weights,
-                prev_layer_size: layer.previous_size as u32,
-                layer_size: layer.size as u32,
-            }
+    StandardShaderPipeline {
+        pipeline,
+        invocations: invocations as u32,
+        layer_size: layer_size as u32,
+    }
+}
+
+/// Compiles the shader which computes the derivZ values of a layer using the derivZ values of the layer after it.
+pub fn compile_backpropagation(gpu: &GpuDeviceData, layer_size: Size, next_layer_size: Size, invocations: usize) -> TiledShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.backpropagation,
+        "Backpropagation",
+        "backprop_from_layer",
+        hash_map! {
+            "layer_size".to_owned() => layer_size as f64,
+            "next_layer_size".to_owned() => next_layer_size as f64,
+            "invocations".to_owned() => invocations as f64,
         }
+    );
+
+    TiledShaderPipeline {
+        pipeline,
+        invocations: invocations as u32,
+        layer_size: layer_size as u32,
+        tile_size: gpu.shader_components.tile_size,
+    }
+}
+
+/// Compiles the shader which adds the derivatives of the biases to their gradients. See `compile_apply_gradients`
+pub fn compile_apply_backprop_biases(gpu: &GpuDeviceData, layer_size: Size, invocations: usize) -> BackpropApplyBiasShaderPipeline {
+    let reduction = reduction(gpu, invocations, layer_size, APPLY_BIASES_WORKGROUP_SIZE);
+
+    // This shader is a little special, it has some hacks around the lack of good pipeline overridable constant support
+    let component = apply_backprop_biases(&gpu.device, reduction.workers_per_node);
+
+    let constants = hash_map! {
+        "layer_size".to_owned() => layer_size as f64,
+        "invocations".to_owned() => invocations as f64,
+        "chunk_size".to_owned() => reduction.chunk_size as f64,
+        "chunks".to_owned() => reduction.chunks as f64,
+    };
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &component,
+        "Backprop apply biases",
+        "apply_biases",
+        constants.clone()
+    );
+    let combine_pipeline = (reduction.chunks > 1).then(|| create_pipeline(
+        &gpu.device,
+        &component,
+        "Backprop apply biases (combine chunks)",
+        "apply_biases_from_chunks",
+        constants
+    ));
+
+    BackpropApplyBiasShaderPipeline {
+        pipeline,
+        combine_pipeline,
+        partial_sums: partial_sums_buffer(gpu, &reduction, layer_size),
+        layer_size: layer_size as u32,
+        chunks: reduction.chunks as u32,
+    }
+}
+
+/// Compiles the shader which adds the derivatives of the weights of a layer, as well as its biases, to their gradients.
+/// See `compile_apply_gradients`
+pub fn compile_apply_backprop_weights(gpu: &GpuDeviceData, previous_layer_size: Size, layer_size: Size, invocations: usize) -> BackpropApplyWeightShaderPipeline {
+    // The biases are applied together with the weights, as if they were an extra node in the previous layer
+    let parameters = (previous_layer_size + 1) * layer_size;
+    let reduction = reduction(gpu, invocations, parameters, APPLY_WEIGHTS_WORKGROUP_SIZE);
+
+    // This shader is a little special, it has some hacks around the lack of good pipeline overridable constant support
+    let component = apply_backprop_weights(&gpu.device, reduction.workers_per_node);
+
+    let constants = hash_map! {
+        "previous_layer_size".to_owned() => previous_layer_size as f64,
+        "layer_size".to_owned() => layer_size as f64,
+        "invocations".to_owned() => invocations as f64,
+        "chunk_size".to_owned() => reduction.chunk_size as f64,
+        "chunks".to_owned() => reduction.chunks as f64,
+    };
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &component,
+        "Backprop apply weights",
+        "apply_weights",
+        constants.clone()
+    );
+    let combine_pipeline = (reduction.chunks > 1).then(|| create_pipeline(
+        &gpu.device,
+        &component,
+        "Backprop apply weights (combine chunks)",
+        "apply_weights_from_chunks",
+        constants
+    ));
+
+    BackpropApplyWeightShaderPipeline {
+        pipeline,
+        combine_pipeline,
+        partial_sums: partial_sums_buffer(gpu, &reduction, parameters),
+        prev_layer_size: previous_layer_size as u32,
+        layer_size: layer_size as u32,
+        chunks: reduction.chunks as u32,
+    }
+}
+
+pub fn compile_apply_backprop_weights_one_hot(gpu: &GpuDeviceData, input_size: Size, layer_size: Size) -> ApplyOneHotWeightShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.apply_backprop_weights_one_hot,
+        "Backprop apply weights (one-hot)",
+        "apply_weights_one_hot",
+        hash_map! {
+            "input_size".to_owned() => input_size as f64,
+            "layer_size".to_owned() => layer_size as f64,
+        }
+    );
+
+    ApplyOneHotWeightShaderPipeline {
+        pipeline,
+        input_size: input_size as u32,
+        layer_size: layer_size as u32,
+    }
+}
+
+/// Compiles the shader which applies the gradients that the apply shaders accumulated over every part of the data.
+/// `invocations` is the total over all parts
+pub fn compile_apply_gradients(gpu: &GpuDeviceData, size: Size, invocations: usize) -> ApplyGradientsShaderPipeline {
+    let pipeline = create_pipeline(
+        &gpu.device,
+        &gpu.shader_components.apply_gradients,
+        "Apply gradients",
+        "apply_gradients",
+        hash_map! {
+            "size".to_owned() => size as f64,
+            "invocations".to_owned() => invocations as f64,
+        }
+    );
+
+    ApplyGradientsShaderPipeline {
+        pipeline,
+        workgroups: ceil_div(size as u32, ApplyGradientsShaderPipeline::WORKGROUP_SIZE),
+        max_workgroups: gpu.device.limits().max_compute_workgroups_per_dimension,
     }
 }
 
 // Constants here should match the ones in lib.wgsl
-const STD_WORKGROUP_SIZE: (u64, u64, u64) = (32, 2, 1);
+pub(crate) const STD_WORKGROUP_SIZE: (u64, u64, u64) = (32, 2, 1);
+
+/// The size of the workgroups of each shader, and how much workgroup memory they use (in bytes).
+/// For the apply shaders this assumes a single worker per node, they only use more if the device allows it.
+pub fn workgroup_sizes(gpu: &GpuDeviceData) -> Vec<(&'static str, [u32; 3], u64)> {
+    let tile = gpu.shader_components.tile_size;
+    let std = [STD_WORKGROUP_SIZE.0 as u32, STD_WORKGROUP_SIZE.1 as u32, STD_WORKGROUP_SIZE.2 as u32];
+    vec![
+        ("compute_forwards", [tile, tile, 1], 2 * (tile * tile) as u64 * size_of::<MainType>()),
+        ("compute_forwards_one_hot", std, 0),
+        ("backpropagation_start", std, 0),
+        ("backpropagation", [tile, tile, 1], 2 * (tile * tile) as u64 * size_of::<MainType>()),
+        ("apply_backprop_biases", [1, APPLY_BIASES_WORKGROUP_SIZE as u32, 1], APPLY_BIASES_WORKGROUP_SIZE as u64 * size_of::<MainType>()),
+        ("apply_backprop_weights", [1, APPLY_WEIGHTS_WORKGROUP_SIZE as u32, 1], APPLY_WEIGHTS_WORKGROUP_SIZE as u64 * size_of::<MainType>()),
+        ("apply_backprop_weights_one_hot", [8, 8, 1], 0),
+        ("apply_gradients", [ApplyGradientsShaderPipeline::WORKGROUP_SIZE as u32, 1, 1], 0),
+    ]
+}
 
 pub struct StandardShaderPipeline {
     pipeline: ComputePipeline,
@@ -281,9 +517,34 @@ impl StandardShaderPipeline {
     }
 }
 
+/// Used by the shaders which do a tiled matrix multiplication.
+/// Each workgroup handles a square of `tile_size` invocations by `tile_size` nodes.
+pub struct TiledShaderPipeline {
+    pipeline: ComputePipeline,
+    invocations: u32,
+    layer_size: u32,
+    tile_size: u32,
+}
+
+impl TiledShaderPipeline {
+    pub fn setup_pass<'a, 'b: 'a>(&'b self, pass: &mut ComputePass<'a>) {
+        pass.set_pipeline(&self.pipeline);
+        pass.dispatch_workgroups(
+            ceil_div(self.invocations, self.tile_size as u64),
+            ceil_div(self.layer_size, self.tile_size as u64),
+            1
+        )
+    }
+}
+
 pub struct BackpropApplyBiasShaderPipeline {
     pipeline: ComputePipeline,
+    /// Adds up the partial sums of each chunk. None if there's only one chunk
+    combine_pipeline: Option<ComputePipeline>,
+    /// Should be bound at binding 2
+    pub partial_sums: Buffer,
     layer_size: u32,
+    chunks: u32,
 }
 
 impl BackpropApplyBiasShaderPipeline {
@@ -292,10 +553,18 @@ impl BackpropApplyBiasShaderPipeline {
         // Should match constant in `apply_backprop_biases.wgsl`
         const WORKGROUP_SIZE: u32 = 8;
         pass.dispatch_workgroups(
-            1,
+            self.chunks,
             ceil_div(self.layer_size, WORKGROUP_SIZE as u64),
             1,
-        )
+        );
+        if let Some(combine_pipeline) = &self.combine_pipeline {
+            pass.set_pipeline(combine_pipeline);
+            pass.dispatch_workgroups(
+                1,
+                ceil_div(self.layer_size, WORKGROUP_SIZE as u64),
+                1,
+            );
+        }
     }
 
     pub fn get_layout(&self) -> BindGroupLayout{
@@ -305,8 +574,13 @@ impl BackpropApplyBiasShaderPipeline {
 
 pub struct BackpropApplyWeightShaderPipeline {
     pipeline: ComputePipeline,
+    /// Adds up the partial sums of each chunk. None if there's only one chunk
+    combine_pipeline: Option<ComputePipeline>,
+    /// Should be bound at binding 4
+    pub partial_sums: Buffer,
     prev_layer_size: u32,
     layer_size: u32,
+    chunks: u32,
 }
 
 impl BackpropApplyWeightShaderPipeline {
@@ -315,14 +589,65 @@ impl BackpropApplyWeightShaderPipeline {
         // Should match constant in `apply_backprop_weights.wgsl`
         const WORKGROUP_SIZE_A: u32 = 8;
         const WORKGROUP_SIZE_B: u32 = 1;
+        // The extra node in the previous layer is for the biases
         pass.dispatch_workgroups(
-            1,
-            ceil_div(self.prev_layer_size, WORKGROUP_SIZE_A as u64),
+            self.chunks,
+            ceil_div(self.prev_layer_size + 1, WORKGROUP_SIZE_A as u64),
             ceil_div(self.layer_size, WORKGROUP_SIZE_B as u64),
-        )
+        );
+        if let Some(combine_pipeline) = &self.combine_pipeline {
+            pass.set_pipeline(combine_pipeline);
+            pass.dispatch_workgroups(
+                1,
+                ceil_div(self.prev_layer_size + 1, WORKGROUP_SIZE_A as u64),
+                ceil_div(self.layer_size, WORKGROUP_SIZE_B as u64),
+            );
+        }
     }
 
     pub fn get_layout(&self) -> BindGroupLayout{
         self.pipeline.get_bind_group_layout(0)
     }
 }
+
+
+pub struct ApplyOneHotWeightShaderPipeline {
+    pipeline: ComputePipeline,
+    input_size: u32,
+    layer_size: u32,
+}
+
+impl ApplyOneHotWeightShaderPipeline {
+    pub fn setup_pass<'a, 'b: 'a>(&'b self, pass: &mut ComputePass<'a>) {
+        pass.set_pipeline(&self.pipeline);
+        // Should match constant in `apply_backprop_weights_one_hot.wgsl`
+        const WORKGROUP_SIZE_A: u32 = 8;
+        const WORKGROUP_SIZE_B: u32 = 8;
+        pass.dispatch_workgroups(
+            ceil_div(self.input_size, WORKGROUP_SIZE_A as u64),
+            ceil_div(self.layer_size, WORKGROUP_SIZE_B as u64),
+            1,
+        )
+    }
+}
+pub struct ApplyGradientsShaderPipeline {
+    pipeline: ComputePipeline,
+    workgroups: u32,
+    max_workgroups: u32,
+}
+
+impl ApplyGradientsShaderPipeline {
+    // Should match constant in `apply_gradients.wgsl`
+    const WORKGROUP_SIZE: u64 = 64;
+
+    pub fn setup_pass<'a, 'b: 'a>(&'b self, pass: &mut ComputePass<'a>) {
+        pass.set_pipeline(&self.pipeline);
+        // Large layers don't fit in a single dimension, the shader spills over into the y axis
+        let x = self.workgroups.min(self.max_workgroups);
+        pass.dispatch_workgroups(
+            x,
+            ceil_div(self.workgroups, x as u64),
+            1,
+        )
+    }
+}
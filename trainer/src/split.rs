@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, HashSet};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{color::Color, input::Config, layer::MainType, normalize, string::fnv1a};
+
+/// Decides which names are used for training and which for checking. `percentage_training` sets the size of the training set
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Split {
+    /// Each name is picked at random, in order. Adding a name changes the split of every name after it
+    #[default]
+    Random,
+    /// Each name is picked by a hash of its normalised form, so a name always ends up in the same set
+    Hash,
+    /// The names are grouped by the lightness and hue of their colour, and each group is split by hash.
+    /// Colours with little chroma get a group of their own, per lightness
+    Stratified { lightness_bins: usize, hue_bins: usize },
+    /// Words are held out by hash, and every name containing a held-out word goes in the checking set.
+    /// Checks how well the network does on words it's never seen. As names have several words,
+    /// the checking set ends up larger than `1 - percentage_training`
+    HeldOutWords,
+}
+
+/// Colours with less chroma than this don't have a meaningful hue
+pub(crate) const GREY_CHROMA: MainType = 0.02;
+
+/// In which set a name ended up
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub source: String,
+    pub training: bool,
+    /// The stratification group, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Returns whether each name goes in the training set, and the group it was stratified in
+pub fn assign(names: &[(String, Color)], config: &Config) -> Vec<(bool, Option<String>)> {
+    let ratio = config.percentage_training;
+    // A fraction in [0, 1) that's stable for each string
+    let fraction = |str: &str| fnv1a(str) as f64 / (u32::MAX as f64 + 1.0);
+
+    match config.split {
+        Split::Random => {
+            // Random number, chosen by fair dice roll
+            // (having this be deterministic should help reproducability)
+            let mut rand = ChaCha20Rng::from_seed([4; 32]);
+            names.iter().map(|_| (rand.gen_ratio((10000f64*ratio) as u32, 10000), None)).collect()
+        }
+        Split::Hash => names.iter().map(|(name, _)| (fraction(&normalize::prepare(name, config)) < ratio, None)).collect(),
+        Split::Stratified { lightness_bins, hue_bins } => {
+            assert!(lightness_bins > 0 && hue_bins > 0, "Stratified splits need at least one bin");
+            let groups: Vec<String> = names.iter().map(|(_, color)| group(*color, lightness_bins, hue_bins)).collect();
+            let mut members = BTreeMap::<&str, Vec<usize>>::new();
+            for (i, group) in groups.iter().enumerate() {
+                members.entry(group).or_default().push(i);
+            }
+
+            let mut output = vec![false; names.len()];
+            for indices in members.values() {
+                // The order within a group only depends on the names, so adding one only moves names around it
+                let mut indices: Vec<_> = indices.iter().map(|i| (fraction(&normalize::prepare(&names[*i].0, config)), *i)).collect();
+                indices.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                let training = (indices.len() as f64 * ratio).round() as usize;
+                for (_, i) in &indices[..training] {
+                    output[*i] = true;
+                }
+            }
+            output.into_iter().zip(groups).map(|(training, group)| (training, Some(group))).collect()
+        }
+        Split::HeldOutWords => {
+            let prepared: Vec<_> = names.iter().map(|(name, _)| normalize::prepare(name, config)).collect();
+            let held_out: HashSet<&str> = prepared.iter()
+                .flat_map(|name| name.split_whitespace())
+                .filter(|word| fraction(word) >= ratio)
+                .collect();
+            prepared.iter().map(|name| (!name.split_whitespace().any(|w| held_out.contains(w)), None)).collect()
+        }
+    }
+}
+
+/// The stratification group of a colour, such as "l2-h5" or "l0-grey"
+fn group(color: Color, lightness_bins: usize, hue_bins: usize) -> String {
+    let (l, a, b) = color.to_oklab();
+    let lightness = ((l.clamp(0.0, 1.0) * lightness_bins as MainType) as usize).min(lightness_bins - 1);
+    if a.hypot(b) < GREY_CHROMA {
+        return format!("l{lightness}-grey");
+    }
+    let turns = b.atan2(a) / std::f32::consts::TAU as MainType;
+    let hue = ((turns.rem_euclid(1.0) * hue_bins as MainType) as usize).min(hue_bins - 1);
+    format!("l{lightness}-h{hue}")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use crate::{color::Color, input::Config};
+
+    use super::assign;
+
+    fn names(count: usize) -> Vec<(String, Color)> {
+        let words = ["dark", "light", "blue", "green", "red", "pale", "deep", "grey", "pink", "brown", "olive", "sky"];
+        (0..count).map(|i| {
+            let name = format!("{} {} {i}", words[i % words.len()], words[(i / words.len()) % words.len()]);
+            let color = Color::from_rgb(((i % 7) as f32 / 6.0, (i % 5) as f32 / 4.0, (i % 3) as f32 / 2.0));
+            (name, color)
+        }).collect()
+    }
+
+    #[test]
+    fn hash_split_is_stable() {
+        let config = Config::test(r#"{ "percentage_training": 0.7, "split": { "strategy": "hash" } }"#);
+        let names = names(500);
+        let split = assign(&names, &config);
+        let training = split.iter().filter(|s| s.0).count();
+        assert!((300..400).contains(&training), "{training}");
+
+        // Adding a name doesn't move any of the others
+        let mut more = names.clone();
+        more.insert(0, ("a new name".to_owned(), names[0].1));
+        assert_eq!(assign(&more, &config)[1..], split);
+        // Neither does changing the case
+        let mut shouted = names.clone();
+        shouted[3].0 = shouted[3].0.to_uppercase();
+        assert_eq!(assign(&shouted, &config), split);
+    }
+
+    #[test]
+    fn stratifies_by_colour() {
+        let names = names(600);
+        let split = assign(&names, &Config::test(r#"{ "percentage_training": 0.7, "split": { "strategy": "stratified", "lightness_bins": 3, "hue_bins": 4 } }"#));
+        let groups: HashSet<_> = split.iter().map(|s| s.1.clone().unwrap()).collect();
+        assert!(groups.len() > 4);
+        for group in groups {
+            let members: Vec<_> = split.iter().filter(|s| s.1.as_ref() == Some(&group)).collect();
+            let training = members.iter().filter(|s| s.0).count();
+            assert_eq!(training, (members.len() as f64 * 0.7).round() as usize, "{group}");
+        }
+    }
+
+    #[test]
+    fn holds_out_words() {
+        let names = names(500);
+        let split = assign(&names, &Config::test(r#"{ "percentage_training": 0.7, "split": { "strategy": "held_out_words" } }"#));
+        let words = |training: bool| -> HashSet<String> {
+            names.iter().zip(&split).filter(|(_, s)| s.0 == training).flat_map(|(n, _)| n.0.split_whitespace().map(str::to_owned)).collect()
+        };
+        let training_words = words(true);
+        let checking: Vec<_> = names.iter().zip(&split).filter(|(_, s)| !s.0).collect();
+        assert!(!checking.is_empty() && checking.len() < names.len());
+        for (name, _) in checking {
+            assert!(name.0.split_whitespace().any(|w| !training_words.contains(w)), "{:?} only has known words", name.0);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{color::Color, input::{Config, TrainingDataRaw}, layer::MainType, normalize, split::GREY_CHROMA, string::{encode, fitting_length, Layout}};
+
+const LIGHTNESS_BINS: usize = 10;
+const HUE_BINS: usize = 12;
+/// How many of the most common words and unknown characters are shown
+const TOP: usize = 20;
+/// The width of the longest bar in the histograms
+const BAR_WIDTH: usize = 40;
+
+/// What the training data looks like, to help choose the input encoding
+pub struct DatasetStats {
+    /// The length of each name in characters, after normalisation. Sorted
+    pub lengths: Vec<usize>,
+    /// The smallest `input_length` at which each name isn't truncated, see `fitting_length`. Sorted, with the names
+    /// that never fit last
+    pub fitting_lengths: Vec<Option<usize>>,
+    /// The configured `input_length`
+    pub input_length: usize,
+    /// The configured layout, which decides how many positions a name needs
+    pub layout: Layout,
+    /// The names which don't fit in the input
+    pub truncated: Vec<String>,
+    pub classes: CharClasses,
+    /// The characters which go into the unknown node of the alphabet, most common first
+    pub unknown_chars: Vec<(char, usize)>,
+    /// Counts of OkLab lightness, in bins of 0.1
+    pub lightness: [usize; LIGHTNESS_BINS],
+    /// Counts of OkLab hue, in bins of 30 degrees starting at pinkish red. Greys aren't counted
+    pub hues: [usize; HUE_BINS],
+    /// Colours with too little chroma to have a hue
+    pub greys: usize,
+    /// The most common words, most common first
+    pub words: Vec<(String, usize)>,
+    /// The percentage of names `recommended_input_length` should fit
+    pub percentile: f64,
+}
+
+/// How often each kind of character occurs, after normalisation
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct CharClasses {
+    pub letters: usize,
+    pub digits: usize,
+    pub punctuation: usize,
+    pub whitespace: usize,
+    /// Characters which go into the unknown node
+    pub unknown: usize,
+}
+
+impl DatasetStats {
+    /// The colours should've been checked by `check_colors`
+    pub fn new(data: &TrainingDataRaw, config: &Config, percentile: f64) -> Self {
+        let alphabet = &config.alphabet;
+        let letters = alphabet.letters.chars().count();
+        let unknown_node = alphabet.size() as usize - 1;
+
+        let mut stats = Self {
+            lengths: Vec::with_capacity(data.len()),
+            fitting_lengths: Vec::with_capacity(data.len()),
+            input_length: config.input_length_max_chars() as usize,
+            layout: config.layout,
+            truncated: Vec::new(),
+            classes: CharClasses::default(),
+            unknown_chars: Vec::new(),
+            lightness: [0; LIGHTNESS_BINS],
+            hues: [0; HUE_BINS],
+            greys: 0,
+            words: Vec::new(),
+            percentile,
+        };
+        let mut unknown_chars = HashMap::<char, usize>::new();
+        let mut words = HashMap::<&str, usize>::new();
+
+        let prepared: Vec<_> = data.iter().map(|entry| normalize::prepare(&entry.name, config)).collect();
+        for (entry, name) in data.iter().zip(&prepared) {
+            stats.lengths.push(name.chars().count());
+            stats.fitting_lengths.push(fitting_length(&entry.name, config));
+            if encode(&entry.name, config).truncated {
+                stats.truncated.push(entry.name.clone());
+            }
+
+            for c in name.chars() {
+                match alphabet.char_to_num(c) {
+                    None => stats.classes.whitespace += 1,
+                    Some(n) if n == unknown_node => {
+                        stats.classes.unknown += 1;
+                        *unknown_chars.entry(c).or_default() += 1;
+                    }
+                    Some(n) if n < letters => stats.classes.letters += 1,
+                    Some(n) if alphabet.digits && n < letters + 10 => stats.classes.digits += 1,
+                    Some(_) => stats.classes.punctuation += 1,
+                }
+            }
+            for word in name.split_whitespace() {
+                *words.entry(word).or_default() += 1;
+            }
+
+            let color = Color::from_str(&entry.color).unwrap_or_else(|e| panic!("Invalid colour for {:?}: {e}", entry.name));
+            let (l, a, b) = color.to_oklab();
+            stats.lightness[((l.clamp(0.0, 1.0) * LIGHTNESS_BINS as MainType) as usize).min(LIGHTNESS_BINS - 1)] += 1;
+            if a.hypot(b) < GREY_CHROMA {
+                stats.greys += 1;
+            } else {
+                let turns = (b.atan2(a) / std::f32::consts::TAU as MainType).rem_euclid(1.0);
+                stats.hues[((turns * HUE_BINS as MainType) as usize).min(HUE_BINS - 1)] += 1;
+            }
+        }
+        stats.lengths.sort_unstable();
+        stats.fitting_lengths.sort_unstable_by_key(|l| l.unwrap_or(usize::MAX));
+
+        stats.unknown_chars = most_common(unknown_chars.into_iter());
+        stats.words = most_common(words.into_iter().map(|(w, c)| (w.to_owned(), c)));
+        stats
+    }
+
+    /// The shortest `input_length` at which the configured layout doesn't truncate `percentile` percent of the names.
+    /// None if too many names can't be fit at any length
+    pub fn recommended_input_length(&self) -> Option<usize> {
+        if self.fitting_lengths.is_empty() {
+            return Some(0);
+        }
+        let index = ((self.percentile / 100.0 * self.fitting_lengths.len() as f64).ceil() as usize).clamp(1, self.fitting_lengths.len()) - 1;
+        self.fitting_lengths[index]
+    }
+}
+
+/// The `TOP` most common items. Ties are sorted by the item, so the output doesn't depend on the order of a hash map
+fn most_common<T: Ord>(counts: impl Iterator<Item = (T, usize)>) -> Vec<(T, usize)> {
+    let mut counts: Vec<_> = counts.collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.truncate(TOP);
+    counts
+}
+
+/// Writes one row per bucket with a bar proportional to the count
+fn histogram(f: &mut std::fmt::Formatter<'_>, rows: impl IntoIterator<Item = (String, usize)>) -> std::fmt::Result {
+    let rows: Vec<_> = rows.into_iter().collect();
+    let max = rows.iter().map(|r| r.1).max().unwrap_or(0).max(1);
+    for (label, count) in rows {
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(max));
+        writeln!(f, "{}", format!("  {label:>12} {count:>7} {bar}").trim_end())?;
+    }
+    Ok(())
+}
+
+impl Display for DatasetStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self.lengths.len();
+        writeln!(f, "{names} names")?;
+        writeln!(f)?;
+
+        writeln!(f, "Name length (characters after normalisation):")?;
+        let longest = self.lengths.last().copied().unwrap_or(0);
+        let bin = longest.div_ceil(20).max(1);
+        histogram(f, (0..=longest / bin).map(|i| {
+            let (start, end) = (i * bin, i * bin + bin - 1);
+            let label = if bin == 1 { start.to_string() } else { format!("{start}-{end}") };
+            (label, self.lengths.iter().filter(|l| (start..=end).contains(l)).count())
+        }))?;
+        writeln!(f)?;
+
+        writeln!(f, "{} names are truncated at the configured input_length of {}:", self.truncated.len(), self.input_length)?;
+        for name in self.truncated.iter().take(TOP) {
+            writeln!(f, "  {name:?}")?;
+        }
+        if self.truncated.len() > TOP {
+            writeln!(f, "  and {} more", self.truncated.len() - TOP)?;
+        }
+        writeln!(f)?;
+
+        let classes = &self.classes;
+        writeln!(f, "Characters:")?;
+        histogram(f, [
+            ("letters".to_owned(), classes.letters),
+            ("digits".to_owned(), classes.digits),
+            ("punctuation".to_owned(), classes.punctuation),
+            ("whitespace".to_owned(), classes.whitespace),
+            ("unknown".to_owned(), classes.unknown),
+        ])?;
+        if !self.unknown_chars.is_empty() {
+            let unknown: Vec<_> = self.unknown_chars.iter().map(|(c, count)| format!("{c:?} {count}")).collect();
+            writeln!(f, "  Most common unknown characters: {}", unknown.join(", "))?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "OkLab lightness:")?;
+        histogram(f, self.lightness.iter().enumerate().map(|(i, count)| {
+            (format!("{:.1}-{:.1}", i as f64 / LIGHTNESS_BINS as f64, (i + 1) as f64 / LIGHTNESS_BINS as f64), *count)
+        }))?;
+        writeln!(f, "OkLab hue:")?;
+        let degrees = 360 / HUE_BINS;
+        histogram(f, self.hues.iter().enumerate().map(|(i, count)| (format!("{}-{}°", i * degrees, (i + 1) * degrees), *count))
+            .chain([("grey".to_owned(), self.greys)]))?;
+        writeln!(f)?;
+
+        let words: Vec<_> = self.words.iter().map(|(w, count)| format!("{w} {count}")).collect();
+        writeln!(f, "Most common words: {}", words.join(", "))?;
+        writeln!(f)?;
+
+        match self.recommended_input_length() {
+            Some(recommended) => writeln!(f, "With the {:?} layout, an input_length of {recommended} fits {}% of the names (currently {})", self.layout, self.percentile, self.input_length)?,
+            None => {
+                let never = self.fitting_lengths.iter().filter(|l| l.is_none()).count();
+                writeln!(f, "With the {:?} layout, no input_length fits {}% of the names, {never} names have a word that's longer than a slot", self.layout, self.percentile)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::{Config, RawEntry};
+
+    use super::{CharClasses, DatasetStats};
+
+    #[test]
+    fn gathers_stats() {
+        let config = Config::test(r#"{ "input_length": 8, "normalize": { "qualifiers": "keep" } }"#);
+        let data = vec![
+            RawEntry::new("red", "#ff0000"),
+            RawEntry::new("dark red", "#8b0000"),
+            RawEntry::new("Red 2", "#ee0000"),
+            RawEntry::new("très grey!", "#808080"),
+            RawEntry::new("very dark greyish blue", "#202030"),
+        ];
+        let stats = DatasetStats::new(&data, &config, 80.0);
+
+        assert_eq!(stats.lengths, vec![3, 5, 8, 10, 22]);
+        assert_eq!(stats.truncated, vec!["très grey!", "very dark greyish blue"]);
+        assert_eq!(stats.classes, CharClasses { letters: 39, digits: 0, punctuation: 0, whitespace: 6, unknown: 3 });
+        assert_eq!(stats.unknown_chars, vec![('!', 1), ('2', 1), ('è', 1)]);
+        assert_eq!(stats.words[..2], [("red".to_owned(), 3), ("dark".to_owned(), 2)]);
+        assert_eq!(stats.greys, 1);
+        assert_eq!(stats.hues.iter().sum::<usize>(), 4);
+        assert_eq!(stats.lightness.iter().sum::<usize>(), 5);
+
+        // The last word is written separately, so the space in front of it doesn't take a position
+        assert_eq!(stats.fitting_lengths, vec![Some(3), Some(4), Some(7), Some(9), Some(21)]);
+        assert_eq!(stats.recommended_input_length(), Some(9));
+        assert_eq!(DatasetStats::new(&data, &config, 100.0).recommended_input_length(), Some(21));
+        assert_eq!(DatasetStats::new(&data, &config, 1.0).recommended_input_length(), Some(3));
+        // Doesn't panic
+        stats.to_string();
+    }
+
+    #[test]
+    fn recommends_length_for_layout() {
+        let data: Vec<_> = ["red", "dark red", "red 2", "très grey!", "very dark greyish blue"].into_iter().map(|n| RawEntry::new(n, "#ff0000")).collect();
+        let stats = |layout: &str, percentile: f64| {
+            let config = Config::test(&format!(r#"{{ "input_length": 8, "normalize": {{ "qualifiers": "keep" }}, "layout": {layout} }}"#));
+            DatasetStats::new(&data, &config, percentile)
+        };
+
+        let left = stats(r#"{ "strategy": "left" }"#, 80.0);
+        assert_eq!(left.fitting_lengths, vec![Some(3), Some(5), Some(8), Some(10), Some(22)]);
+        assert_eq!(left.recommended_input_length(), Some(10));
+
+        let bag = stats(r#"{ "strategy": "bag_of_words" }"#, 100.0);
+        assert_eq!(bag.recommended_input_length(), Some(19));
+
+        // Words longer than the slots never fit
+        let slots = stats(r#"{ "strategy": "word_slots", "slot_length": 4 }"#, 60.0);
+        assert_eq!(slots.fitting_lengths, vec![Some(4), Some(8), Some(8), None, None]);
+        assert_eq!(slots.recommended_input_length(), Some(8));
+        let slots = stats(r#"{ "strategy": "word_slots", "slot_length": 4 }"#, 100.0);
+        assert_eq!(slots.recommended_input_length(), None);
+        assert!(slots.to_string().contains("no input_length fits 100% of the names, 2 names"));
+    }
+}
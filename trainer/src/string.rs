@@ -1,41 +1,499 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
 use crate::{input::Config, layer::Size, training_data::GpuInputData};
 
-pub(crate) fn get_input_size(configured_input_length: Size) -> Size {
-    return configured_input_length * 27;
+/// Used to pad out the list of active inputs. Should match the constant in the one-hot shaders
+pub const NO_INPUT: u32 = u32::MAX;
+
+pub(crate) fn get_input_size(configured_input_length: Size, alphabet: &Alphabet) -> Size {
+    return configured_input_length * alphabet.size();
+}
+
+/// Decides which input node each character activates. Every character position has a node for each letter,
+/// optionally a node for each digit and one shared by all punctuation, and a node for everything else.
+/// This is part of the config, so the trainer and everything that runs the network encode names the same way.
+/// This logic MUST match the one in `NNConfig.Alphabet` on the java side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct Alphabet {
+    /// Each of these characters gets its own node. Characters are lowercased before they're looked up
+    pub letters: String,
+    /// Decompose characters (NFKD) and drop their diacritics, so "é" is encoded as "e"
+    pub fold_diacritics: bool,
+    /// Give each of 0-9 its own node
+    pub digits: bool,
+    /// Give ascii punctuation its own node, instead of sharing the unknown node
+    pub punctuation: bool,
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Self {
+            letters: "abcdefghijklmnopqrstuvwxyz".to_owned(),
+            fold_diacritics: false,
+            digits: false,
+            punctuation: false,
+        }
+    }
+}
+
+impl Alphabet {
+    /// The amount of nodes for each character position
+    pub fn size(&self) -> Size {
+        let mut size = self.letters.chars().count() as Size;
+        if self.digits {
+            size += 10;
+        }
+        if self.punctuation {
+            size += 1;
+        }
+        // The unknown node
+        return size + 1;
+    }
+
+    /// Applies the folding. This needs to happen before the string is laid out, as folding can change the amount of characters
+    pub fn normalize(&self, str: &str) -> String {
+        if self.fold_diacritics {
+            return str.nfkd().filter(|c| !is_combining_mark(*c)).collect();
+        } else {
+            return str.to_owned();
+        }
+    }
+
+    /// The node of the character within its position, or None if it shouldn't activate anything
+    pub fn char_to_num(&self, c: char) -> Option<usize> {
+        if c.is_whitespace() {
+            return None;
+        }
+        // Some characters lowercase into multiple, the first one is the base character
+        let c = c.to_lowercase().next().unwrap();
+
+        let letters = self.letters.chars().count();
+        if let Some(i) = self.letters.chars().position(|l| l == c) {
+            return Some(i);
+        }
+        let mut next = letters;
+        if self.digits {
+            if let Some(digit) = c.to_digit(10).filter(|_| c.is_ascii_digit()) {
+                return Some(next + digit as usize);
+            }
+            next += 10;
+        }
+        if self.punctuation {
+            if c.is_ascii_punctuation() {
+                return Some(next);
+            }
+            next += 1;
+        }
+        return Some(next);
+    }
+}
+
+/// The encoded input of a single string
+pub struct Encoded {
+    pub data: GpuInputData,
+    /// Whether some characters didn't fit (see `Layout`), or some hashed features were dropped
+    pub truncated: bool,
 }
 
+/// Encodes the string as a one-hot input for the network. As only a few input nodes will be
+/// active, this returns the indices of those nodes, padded with `NO_INPUT` to `config.active_inputs()`
 pub fn string_to_data(str: &str, config: &Config) -> GpuInputData {
-    let mut output = vec![0.0; config.input_length() as usize];
+    return encode(str, config).data;
+}
 
-    let mut words: Vec<_> = str.split_whitespace().collect();
-    let last_word = words.iter().enumerate().filter(|w| !w.1.starts_with("(")).last().map(|l| l.0);
-    let last_word = last_word.map(|i| words.remove(i));
+/// Like `string_to_data`, but also tells if the string had to be truncated
+pub fn encode(str: &str, config: &Config) -> Encoded {
+    let mut output = Vec::with_capacity(config.active_inputs() as usize);
+    let alphabet = &config.alphabet;
+    let nodes = alphabet.size() as usize;
+    let str = &alphabet.normalize(&config.normalize.normalize(str));
 
-    for (i, char) in itertools::join(words, " ").chars().enumerate() {
-        if let Some(n) = char_to_num(char) {
-            output[i * 27 + n] = 1.0;
+    let (positions, mut truncated) = config.layout.layout(str, config.input_length_max_chars() as usize);
+    for (position, char) in positions {
+        if let Some(n) = alphabet.char_to_num(char) {
+            output.push((position * nodes + n) as u32);
         }
     }
-    if let Some(last_word) = last_word {
-        for (i, char) in last_word.chars().enumerate() {
-            if let Some(n) = char_to_num(char) {
-                output[(config.input_length_max_chars() as usize - i as usize - 1) * 27 + n] = 1.0;
+
+    // The layouts never place more characters than there are positions, so this always fits.
+    // The same node might've been activated twice though
+    output.sort_unstable();
+    output.dedup();
+
+    // The hashed features come after all character positions
+    if let Some(hashed) = &config.hashed_features {
+        let offset = config.input_length_max_chars() * nodes as Size;
+        let (buckets, hashed_truncated) = hashed.buckets(str);
+        output.extend(buckets.into_iter().map(|b| (offset + b) as u32));
+        truncated |= hashed_truncated;
+    }
+
+    assert!(output.len() <= config.active_inputs() as usize && output.iter().all(|n| (*n as Size) < config.input_length()), "{str:?} was laid out incorrectly");
+    output.resize(config.active_inputs() as usize, NO_INPUT);
+    return Encoded {
+        data: output,
+        truncated,
+    };
+}
+
+/// The smallest `input_length` at which `encode` doesn't have to drop any characters of the string, or None if no
+/// length is enough (a word longer than the slots of `WordSlots`). Hashed features don't depend on the length, so they're ignored
+pub fn fitting_length(str: &str, config: &Config) -> Option<usize> {
+    let str = &config.alphabet.normalize(&config.normalize.normalize(str));
+    let (min, slot_length) = match config.layout {
+        Layout::WordSlots { slot_length } => (slot_length as usize, slot_length as usize),
+        _ => (0, 1),
+    };
+    // Every layout fits a string in this many positions, if it fits it at all. More positions never truncate more
+    let max = (str.chars().count() * slot_length).max(min);
+    if config.layout.layout(str, max).1 {
+        return None;
+    }
+    let (mut low, mut high) = (min, max);
+    while low < high {
+        let mid = (low + high) / 2;
+        if config.layout.layout(str, mid).1 { low = mid + 1 } else { high = mid }
+    }
+    Some(low)
+}
+
+/// Decides which of the character positions each character of the input goes in.
+/// Every layout places at most one character per position, so there are never more active inputs than positions
+/// (`BagOfWords` may put multiple characters in a position, but never more characters than there are positions).
+/// This logic MUST match `NNConfig.Layout` on the java side
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Layout {
+    /// The last word that isn't a qualifier in parentheses is written backwards from the right end,
+    /// the rest of the string is written from the left. If they don't fit, the last word is preferred and
+    /// characters are dropped from the middle of the string (the end of the leading words).
+    #[default]
+    HeadAndLastWord,
+    /// The whole string is written from the left, anything that doesn't fit is dropped
+    Left,
+    /// The positions are split into slots of `slot_length`, every word is written from the left of its own slot
+    WordSlots { slot_length: Size },
+    /// Every word is written from the first position, so the order of the words doesn't matter
+    BagOfWords,
+}
+
+impl Layout {
+    /// Returns where each character goes out of `length` positions, and whether anything had to be dropped
+    pub fn layout(&self, str: &str, length: usize) -> (Vec<(usize, char)>, bool) {
+        let words: Vec<_> = str.split_whitespace().collect();
+        match self {
+            Layout::HeadAndLastWord => {
+                return head_and_last_word(words, length);
+            }
+            Layout::Left => {
+                let chars: Vec<_> = itertools::join(words, " ").chars().collect();
+                let truncated = chars.len() > length;
+                return (chars.into_iter().take(length).enumerate().collect(), truncated);
+            }
+            Layout::WordSlots { slot_length } => {
+                let slot_length = *slot_length as usize;
+                assert!(slot_length > 0 && slot_length <= length, "The slot length should be between 1 and the input length");
+                let slots = length / slot_length;
+
+                let mut output = Vec::new();
+                let mut truncated = words.len() > slots;
+                for (slot, word) in words.iter().take(slots).enumerate() {
+                    truncated |= word.chars().count() > slot_length;
+                    output.extend(word.chars().take(slot_length).enumerate().map(|(i, c)| (slot * slot_length + i, c)));
+                }
+                return (output, truncated);
+            }
+            Layout::BagOfWords => {
+                let mut output = Vec::new();
+                let mut truncated = false;
+                for word in words {
+                    for (i, char) in word.chars().enumerate() {
+                        if i >= length || output.len() >= length {
+                            truncated = true;
+                            break;
+                        }
+                        output.push((i, char));
+                    }
+                }
+                return (output, truncated);
             }
         }
     }
+}
+
+fn head_and_last_word(mut words: Vec<&str>, length: usize) -> (Vec<(usize, char)>, bool) {
+    let last_word = words.iter().enumerate().filter(|w| !w.1.starts_with("(")).last().map(|l| l.0);
+    let last_word: Vec<char> = last_word.map(|i| words.remove(i).chars().collect()).unwrap_or_default();
+    let leading: Vec<char> = itertools::join(words, " ").chars().collect();
+
+    let last_word_len = last_word.len().min(length);
+    let leading_len = leading.len().min(length - last_word_len);
+    let truncated = last_word_len < last_word.len() || leading_len < leading.len();
+
+    let mut output = Vec::with_capacity(leading_len + last_word_len);
+    for (i, char) in leading[..leading_len].iter().enumerate() {
+        output.push((i, *char));
+    }
+    for (i, char) in last_word[..last_word_len].iter().enumerate() {
+        output.push((length - i - 1, *char));
+    }
+    return (output, truncated);
+}
 
-    return output;
+/// An extra block of input nodes which doesn't depend on where things are in the name. Each word, and each
+/// character n-gram of each word, is hashed into one of a fixed number of buckets, so "blue" activates
+/// the same nodes in "blue green" and in "green blue".
+/// This logic MUST match the one in `NNConfig.HashedFeatures` on the java side
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HashedFeatures {
+    /// The amount of input nodes in this block
+    pub buckets: Size,
+    /// The lengths of the n-grams. Words are surrounded by "<" and ">" first, so n-grams at the edges of words are distinct
+    #[serde(default = "HashedFeatures::default_ngram_lengths")]
+    pub ngram_lengths: Vec<usize>,
+    /// Also hash each word as a whole
+    #[serde(default = "HashedFeatures::default_words")]
+    pub words: bool,
+    /// The maximum amount of buckets a single name can activate. Further features are dropped
+    #[serde(default = "HashedFeatures::default_max_active")]
+    pub max_active: Size,
 }
 
-fn char_to_num(c: char) -> Option<usize> {
-    let char = c.to_ascii_lowercase();
-    if !char.is_whitespace() {
-        if char.is_ascii() && char.is_alphabetic() {
-            Some((char as u8 - 'a' as u8) as usize)
-        } else {
-            Some(26)
+impl HashedFeatures {
+    fn default_ngram_lengths() -> Vec<usize> {
+        vec![3]
+    }
+
+    fn default_words() -> bool {
+        true
+    }
+
+    fn default_max_active() -> Size {
+        32
+    }
+
+    /// The maximum amount of nodes in this block which can be active at once
+    pub fn active_inputs(&self) -> Size {
+        return self.max_active.min(self.buckets);
+    }
+
+    /// Returns the sorted buckets activated by the string, and whether some features were dropped because of `max_active`
+    pub fn buckets(&self, str: &str) -> (Vec<Size>, bool) {
+        assert!(self.buckets > 0, "There should be at least one bucket");
+        let mut output = Vec::new();
+        let mut truncated = false;
+        let mut add = |feature: &str| {
+            let bucket = fnv1a(feature) as Size % self.buckets;
+            if !output.contains(&bucket) {
+                if (output.len() as Size) < self.max_active {
+                    output.push(bucket);
+                } else {
+                    truncated = true;
+                }
+            }
+        };
+
+        for word in str.to_lowercase().split_whitespace() {
+            let marked: Vec<char> = format!("<{word}>").chars().collect();
+            if self.words {
+                add(&marked.iter().collect::<String>());
+            }
+            for n in &self.ngram_lengths {
+                assert!(*n > 0, "N-grams should be at least a single character");
+                for ngram in marked.windows(*n) {
+                    add(&ngram.iter().collect::<String>());
+                }
+            }
+        }
+
+        output.sort_unstable();
+        return (output, truncated);
+    }
+}
+
+/// 32-bit FNV-1a over the utf-8 bytes. Chosen as it's trivial to implement identically on the java side
+pub(crate) fn fnv1a(str: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in str.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::Config;
+
+    use super::{encode, fnv1a, string_to_data, Alphabet, HashedFeatures, Layout, NO_INPUT};
+
+    fn layout(str: &str, length: usize) -> (Vec<(usize, char)>, bool) {
+        Layout::HeadAndLastWord.layout(str, length)
+    }
+
+    fn positions(str: &str, start: usize) -> Vec<(usize, char)> {
+        str.chars().enumerate().map(|(i, c)| (start + i, c)).collect()
+    }
+
+    #[test]
+    fn default_alphabet_matches_old_encoding() {
+        let alphabet = Alphabet::default();
+        assert_eq!(alphabet.size(), 27);
+        assert_eq!(alphabet.char_to_num('a'), Some(0));
+        assert_eq!(alphabet.char_to_num('Z'), Some(25));
+        assert_eq!(alphabet.char_to_num('é'), Some(26));
+        assert_eq!(alphabet.char_to_num('7'), Some(26));
+        assert_eq!(alphabet.char_to_num(' '), None);
+    }
+
+    #[test]
+    fn folds_diacritics() {
+        let config = Config::test(r#"{ "alphabet": { "fold_diacritics": true } }"#);
+        assert_eq!(string_to_data("Café au lait", &config), string_to_data("Cafe au lait", &config));
+        assert_ne!(string_to_data("Café au lait", &config), string_to_data("Cafx au lait", &config));
+        // Without folding the é is unknown, but still different from an x
+        let config = Config::test("{}");
+        assert_ne!(string_to_data("Café au lait", &config), string_to_data("Cafe au lait", &config));
+        assert_ne!(string_to_data("Café au lait", &config), string_to_data("Cafx au lait", &config));
+    }
+
+    #[test]
+    fn digit_and_punctuation_classes() {
+        let alphabet = Alphabet { digits: true, punctuation: true, ..Default::default() };
+        assert_eq!(alphabet.size(), 26 + 10 + 1 + 1);
+        assert_eq!(alphabet.char_to_num('0'), Some(26));
+        assert_eq!(alphabet.char_to_num('9'), Some(35));
+        assert_eq!(alphabet.char_to_num('\''), Some(36));
+        assert_eq!(alphabet.char_to_num('ö'), Some(37));
+        // Non-ascii digits aren't digits
+        assert_eq!(alphabet.char_to_num('٣'), Some(37));
+
+        let config = Config::test(r#"{ "alphabet": { "letters": "abc", "digits": true } }"#);
+        assert_eq!(config.input_length(), 16 * (3 + 10 + 1));
+    }
+
+    #[test]
+    fn lays_out_words() {
+        let (positions, truncated) = layout("navy blue (Crayola)", 20);
+        assert!(!truncated);
+        let expected: Vec<_> = "navy (Crayola)".chars().enumerate()
+            .chain("blue".chars().enumerate().map(|(i, c)| (19 - i, c)))
+            .collect();
+        assert_eq!(positions, expected);
+    }
+
+    #[test]
+    fn truncates_from_the_middle() {
+        // The last word is kept, the leading words are cut off where they'd run into it
+        let (positions, truncated) = layout("very dark greyish blue", 10);
+        assert!(truncated);
+        let expected: Vec<_> = "very d".chars().enumerate()
+            .chain("blue".chars().enumerate().map(|(i, c)| (9 - i, c)))
+            .collect();
+        assert_eq!(positions, expected);
+
+        // A last word that doesn't fit on its own pushes everything else out
+        let (positions, truncated) = layout("a supercalifragilistic", 5);
+        assert!(truncated);
+        assert_eq!(positions, "super".chars().enumerate().map(|(i, c)| (4 - i, c)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn handles_long_and_multibyte_names() {
+        let config = Config::test("{}");
+        // Cutting these off at 16 bytes would land inside a character
+        for name in ["Ŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝ", "ääääääää öööööööö üüüüüüüü", "a b c d e f g h i j k l m n o p q r s t u v w", "(only) (qualifiers)", ""] {
+            let encoded = encode(name, &config);
+            assert_eq!(encoded.data.len(), config.active_inputs() as usize);
+            assert_eq!(encoded.truncated, name.chars().count() > 16, "{name:?}");
         }
-    } else {
-        None
+        // Characters are counted, not bytes
+        assert!(!encode("ŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝŝ", &config).truncated);
+    }
+
+    #[test]
+    fn left_layout() {
+        assert_eq!(Layout::Left.layout("dark  blue", 16), (positions("dark blue", 0), false));
+        assert_eq!(Layout::Left.layout("very dark greyish blue", 9), (positions("very dark", 0), true));
+    }
+
+    #[test]
+    fn word_slot_layout() {
+        let layout = Layout::WordSlots { slot_length: 5 };
+        let (output, truncated) = layout.layout("dark blue", 16);
+        assert!(!truncated);
+        assert_eq!(output, [positions("dark", 0), positions("blue", 5)].concat());
+
+        // Three slots fit, and every word gets cut off at the end of its slot
+        let (output, truncated) = layout.layout("very dark greyish blue", 16);
+        assert!(truncated);
+        assert_eq!(output, [positions("very", 0), positions("dark", 5), positions("greyi", 10)].concat());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn bag_of_words_layout() {
+        let (output, truncated) = Layout::BagOfWords.layout("blue green", 16);
+        assert!(!truncated);
+        assert_eq!(output, [positions("blue", 0), positions("green", 0)].concat());
+
+        // The order of the words doesn't matter
+        let config = Config::test(r#"{ "layout": { "strategy": "bag_of_words" } }"#);
+        assert_eq!(string_to_data("blue green", &config), string_to_data("green blue", &config));
+        // Repeated characters activate the same node once
+        assert_eq!(string_to_data("red red", &config), string_to_data("red", &config));
+
+        // Never more characters than positions
+        let (output, truncated) = Layout::BagOfWords.layout("aaaa bbbb cccc", 10);
+        assert!(truncated);
+        assert_eq!(output.len(), 10);
+    }
+
+    #[test]
+    fn parses_layout_config() {
+        let config = Config::test(r#"{ "layout": { "strategy": "word_slots", "slot_length": 4 } }"#);
+        assert_eq!(config.layout, Layout::WordSlots { slot_length: 4 });
+        assert_eq!(Config::test("{}").layout, Layout::HeadAndLastWord);
+    }
+
+    #[test]
+    fn hashes_words_independent_of_position() {
+        assert_eq!(fnv1a(""), 0x811c9dc5);
+        assert_eq!(fnv1a("a"), 0xe40c292c);
+
+        let config = Config::test(r#"{ "hashed_features": { "buckets": 64 } }"#);
+        assert_eq!(config.input_length(), 16 * 27 + 64);
+        assert_eq!(config.active_inputs(), 16 + 32);
+
+        let hashed = |str: &str| -> Vec<u32> {
+            string_to_data(str, &config).into_iter().filter(|n| *n != NO_INPUT && *n >= 16 * 27).collect()
+        };
+        assert_eq!(hashed("blue green"), hashed("Green  Blue"));
+        assert_ne!(hashed("blue green"), hashed("blue red"));
+        // "<bl", "blu", "lue", "ue>" and "<blue>", some of which might share a bucket
+        let blue = hashed("blue");
+        assert!(!blue.is_empty() && blue.len() <= 5);
+        assert!(blue.iter().all(|n| (*n as u64) < config.input_length()));
+    }
+
+    #[test]
+    fn limits_hashed_features() {
+        let features = HashedFeatures { buckets: 1000, ngram_lengths: vec![1], words: false, max_active: 4 };
+        assert_eq!(features.buckets("ab"), ({
+            let mut expected: Vec<_> = ["<", "a", "b", ">"].iter().map(|f| fnv1a(f) as u64 % 1000).collect();
+            expected.sort();
+            expected
+        }, false));
+        let (buckets, truncated) = features.buckets("abcdefgh");
+        assert_eq!(buckets.len(), 4);
+        assert!(truncated);
+
+        // Never more than there are buckets
+        let config = Config::test(r#"{ "hashed_features": { "buckets": 3, "ngram_lengths": [1, 2, 3] } }"#);
+        assert_eq!(config.active_inputs(), 16 + 3);
+        let encoded = encode("greyish blue", &config);
+        assert!(!encoded.truncated);
+        assert_eq!(encoded.data.len(), 16 + 3);
+    }
+}
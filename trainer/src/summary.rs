@@ -0,0 +1,102 @@
+use std::fmt::Display;
+
+use crate::{input::{Config, LayerConfig}, layer::{MainType, Size}, misc::{format_bytes, size_of}};
+
+/// The average length of a single parameter in the exported json, eg. "0.012345678,". Measured on a trained network
+const JSON_BYTES_PER_PARAMETER: Size = 12;
+
+/// How large a config is, without needing a gpu
+pub struct Summary {
+    layers: Vec<LayerSummary>,
+}
+
+pub struct LayerSummary {
+    pub config: LayerConfig,
+    pub parameters: Size,
+    /// Multiply-adds needed to compute this layer for a single input
+    pub multiply_adds: Size,
+    /// Bytes needed to store the z, a and derivZ values of this layer for a single input during training
+    pub activation_memory: Size,
+}
+
+impl Summary {
+    pub fn new(config: &Config) -> Self {
+        let layers = config.layers().into_iter().map(|layer| {
+            // One-hot layers only look at the active input nodes
+            let inputs = layer.active_inputs.unwrap_or(layer.previous_size);
+            LayerSummary {
+                config: layer,
+                parameters: layer.previous_size * layer.size + layer.size,
+                multiply_adds: inputs * layer.size,
+                activation_memory: 3 * layer.size * size_of::<MainType>(),
+            }
+        }).collect();
+
+        Self { layers }
+    }
+
+    pub fn parameters(&self) -> Size {
+        self.layers.iter().map(|l| l.parameters).sum()
+    }
+
+    pub fn multiply_adds(&self) -> Size {
+        self.layers.iter().map(|l| l.multiply_adds).sum()
+    }
+
+    pub fn activation_memory(&self) -> Size {
+        self.layers.iter().map(|l| l.activation_memory).sum()
+    }
+
+    /// The size of the parameters as raw f32 values
+    pub fn binary_size(&self) -> Size {
+        self.parameters() * size_of::<MainType>()
+    }
+
+    /// Estimate of the size of the json file the trainer exports. The exact size depends on the values
+    pub fn json_size(&self) -> Size {
+        // Every layer is stored as {"weights":[...],"biases":[...]}
+        let layer_overhead = r#"{"weights":[],"biases":[]},"#.len() as Size;
+        self.parameters() * JSON_BYTES_PER_PARAMETER + self.layers.len() as Size * layer_overhead + 2
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>5} {:>10} {:>10} {:>14} {:>14} {:>20}", "Layer", "Inputs", "Size", "Parameters", "Multiply-adds", "Activations/sample")?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let inputs = match layer.config.active_inputs {
+                Some(active) => format!("{active}/{}", layer.config.previous_size),
+                None => layer.config.previous_size.to_string(),
+            };
+            writeln!(f, "{:>5} {:>10} {:>10} {:>14} {:>14} {:>20}", i, inputs, layer.config.size, layer.parameters, layer.multiply_adds, format_bytes(layer.activation_memory))?;
+        }
+        writeln!(f, "{:>5} {:>10} {:>10} {:>14} {:>14} {:>20}", "Total", "", "", self.parameters(), self.multiply_adds(), format_bytes(self.activation_memory()))?;
+        writeln!(f)?;
+        writeln!(f, "Multiply-adds are per inference. Activations are the z, a and derivZ values kept for each sample during training")?;
+        writeln!(f, "Exported model: about {} as json, {} as raw f32 parameters", format_bytes(self.json_size()), format_bytes(self.binary_size()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::Config;
+
+    use super::Summary;
+
+    #[test]
+    fn counts_parameters_and_work() {
+        let config = Config::test(r#"{ "input_length": 2, "layers": [4, 3] }"#);
+        let summary = Summary::new(&config);
+
+        // 2 characters, one-hot encoded into 54 input nodes
+        assert_eq!(summary.layers[0].parameters, 54 * 4 + 4);
+        // Only the two active input nodes are multiplied
+        assert_eq!(summary.layers[0].multiply_adds, 2 * 4);
+        assert_eq!(summary.layers[1].parameters, 4 * 3 + 3);
+        assert_eq!(summary.layers[1].multiply_adds, 4 * 3);
+        assert_eq!(summary.parameters(), 220 + 15);
+        assert_eq!(summary.binary_size(), 235 * 4);
+        assert_eq!(summary.activation_memory(), 3 * (4 + 3) * 4);
+    }
+}
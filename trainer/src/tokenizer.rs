@@ -0,0 +1,212 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use serde::{de::Error, Deserialize, Serialize};
+
+/// The first tokens are the 256 single bytes, so every string can be encoded
+const BYTE_TOKENS: usize = 256;
+/// Words are separated by a single space
+const SPACE: u32 = b' ' as u32;
+
+/// A byte-pair-encoding vocabulary learned from the colour names. Common pieces of words such as
+/// "ish", "blue" and "dark" become a single token. Merges never cross word boundaries.
+/// This is only the artefact and the command to train it, the network input is still made of characters.
+#[derive(Clone, Debug)]
+pub struct Tokenizer {
+    /// The pairs of tokens which were merged, in the order they were learned.
+    /// Merge `i` creates token `BYTE_TOKENS + i`
+    merges: Vec<(u32, u32)>,
+    ranks: HashMap<(u32, u32), u32>,
+    /// The bytes of each token
+    tokens: Vec<Vec<u8>>,
+}
+
+/// How the tokenizer is stored
+#[derive(Serialize, Deserialize)]
+struct JsonTokenizer {
+    merges: Vec<(u32, u32)>,
+    /// The text of the learned tokens, to make the file readable. Ignored when loading
+    #[serde(default)]
+    vocabulary: Vec<String>,
+}
+
+impl Tokenizer {
+    /// Fails if a merge uses a token that doesn't exist yet, which happens when the file was edited by hand
+    fn from_merges(merges: Vec<(u32, u32)>) -> Result<Self, String> {
+        let mut tokens: Vec<Vec<u8>> = (0..BYTE_TOKENS).map(|b| vec![b as u8]).collect();
+        let mut ranks = HashMap::new();
+        for (i, (a, b)) in merges.iter().enumerate() {
+            if *a as usize >= tokens.len() || *b as usize >= tokens.len() {
+                return Err(format!("Merge {i} ({a}, {b}) uses a token that doesn't exist yet, only {} tokens exist before it", tokens.len()));
+            }
+            tokens.push([tokens[*a as usize].as_slice(), tokens[*b as usize].as_slice()].concat());
+            ranks.insert((*a, *b), i as u32);
+        }
+        Ok(Self { merges, ranks, tokens })
+    }
+
+    /// Learns merges from the (prepared) names until there are `vocab_size` tokens,
+    /// or until no pair of tokens occurs more than once
+    pub fn train<'a>(names: impl IntoIterator<Item = &'a str>, vocab_size: usize) -> Self {
+        assert!(vocab_size >= BYTE_TOKENS, "The vocabulary should at least contain every byte");
+
+        let mut word_counts = HashMap::<&str, usize>::new();
+        for name in names {
+            for word in name.split_whitespace() {
+                *word_counts.entry(word).or_default() += 1;
+            }
+        }
+        let mut words: Vec<(Vec<u32>, usize)> = word_counts.into_iter()
+            .map(|(word, count)| (word.bytes().map(|b| b as u32).collect(), count))
+            .collect();
+
+        let mut merges = Vec::new();
+        while BYTE_TOKENS + merges.len() < vocab_size {
+            let mut pairs = HashMap::<(u32, u32), usize>::new();
+            for (tokens, count) in &words {
+                for pair in tokens.windows(2) {
+                    *pairs.entry((pair[0], pair[1])).or_default() += count;
+                }
+            }
+            // Ties are broken by the smallest pair, so training is deterministic
+            let Some((pair, count)) = pairs.into_iter().max_by_key(|(pair, count)| (*count, Reverse(*pair))) else {
+                break;
+            };
+            // A pair that only occurs once won't help to generalise
+            if count < 2 {
+                break;
+            }
+
+            let token = (BYTE_TOKENS + merges.len()) as u32;
+            for (tokens, _) in &mut words {
+                merge(tokens, pair, token);
+            }
+            merges.push(pair);
+        }
+
+        return Self::from_merges(merges).expect("Learned merges only use earlier tokens");
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        return self.tokens.len();
+    }
+
+    /// The bytes a token stands for
+    pub fn token(&self, token: u32) -> &[u8] {
+        return &self.tokens[token as usize];
+    }
+
+    /// Splits the (prepared) string into tokens. Whitespace is collapsed into a single space token between words
+    pub fn encode(&self, str: &str) -> Vec<u32> {
+        let mut output = Vec::new();
+        for (i, word) in str.split_whitespace().enumerate() {
+            if i > 0 {
+                output.push(SPACE);
+            }
+            output.extend(self.encode_word(word));
+        }
+        return output;
+    }
+
+    fn encode_word(&self, word: &str) -> Vec<u32> {
+        let mut tokens: Vec<u32> = word.bytes().map(|b| b as u32).collect();
+        // Apply the merges in the order they were learned
+        while let Some(rank) = tokens.windows(2).filter_map(|pair| self.ranks.get(&(pair[0], pair[1]))).min() {
+            merge(&mut tokens, self.merges[*rank as usize], BYTE_TOKENS as u32 + rank);
+        }
+        return tokens;
+    }
+
+    pub fn decode(&self, tokens: &[u32]) -> String {
+        let bytes: Vec<u8> = tokens.iter().flat_map(|t| self.token(*t).iter().copied()).collect();
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+}
+
+/// Replaces every occurrence of `pair` with `token`
+fn merge(tokens: &mut Vec<u32>, pair: (u32, u32), token: u32) {
+    let mut output = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if i + 1 < tokens.len() && (tokens[i], tokens[i + 1]) == pair {
+            output.push(token);
+            i += 2;
+        } else {
+            output.push(tokens[i]);
+            i += 1;
+        }
+    }
+    *tokens = output;
+}
+
+impl Serialize for Tokenizer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonTokenizer {
+            merges: self.merges.clone(),
+            vocabulary: self.tokens[BYTE_TOKENS..].iter().map(|t| String::from_utf8_lossy(t).into_owned()).collect(),
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tokenizer {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = JsonTokenizer::deserialize(deserializer)?;
+        Self::from_merges(json.merges).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Tokenizer;
+
+    const NAMES: [&str; 8] = ["dark blue", "light blue", "blueish green", "dark greenish blue", "greyish blue", "dark red", "reddish brown", "light greyish green"];
+
+    #[test]
+    fn learns_common_words() {
+        let tokenizer = Tokenizer::train(NAMES, 300);
+        assert!(tokenizer.vocab_size() > 256 && tokenizer.vocab_size() <= 300);
+
+        let blue = tokenizer.encode("blue");
+        assert_eq!(blue.len(), 1);
+        assert_eq!(tokenizer.token(blue[0]), b"blue");
+        assert_eq!(tokenizer.encode("greyish").len(), 1);
+        // Pieces of words carry over to words the tokenizer hasn't seen
+        assert!(tokenizer.encode("pinkish").len() < "pinkish".len());
+        assert!(tokenizer.encode("dark blue").contains(&(b' ' as u32)));
+
+        // Stops once nothing repeats
+        let tokenizer = Tokenizer::train(["abc"], 1000);
+        assert_eq!(tokenizer.vocab_size(), 256);
+    }
+
+    #[test]
+    fn round_trips() {
+        let tokenizer = Tokenizer::train(NAMES, 300);
+        for name in NAMES.iter().copied().chain(["café au lait", "ミッドナイトブルー", "", "blue 🔵", "#0047ab"]) {
+            assert_eq!(tokenizer.decode(&tokenizer.encode(name)), name);
+        }
+        // Whitespace is collapsed
+        assert_eq!(tokenizer.decode(&tokenizer.encode("  dark \t blue ")), "dark blue");
+    }
+
+    #[test]
+    fn serializes() {
+        let tokenizer = Tokenizer::train(NAMES, 300);
+        let json = serde_json::to_string(&tokenizer).unwrap();
+        assert!(json.contains(r#""blue""#));
+
+        let loaded: Tokenizer = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.vocab_size(), tokenizer.vocab_size());
+        for name in NAMES {
+            assert_eq!(loaded.encode(name), tokenizer.encode(name));
+        }
+        // Training is deterministic
+        assert_eq!(serde_json::to_string(&Tokenizer::train(NAMES, 300)).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_invalid_merges() {
+        let error = serde_json::from_str::<Tokenizer>(r#"{ "merges": [[98, 108], [256, 300]] }"#).unwrap_err();
+        assert!(error.to_string().starts_with("Merge 1 (256, 300) uses a token that doesn't exist yet"), "{error}");
+        assert!(serde_json::from_str::<Tokenizer>(r#"{ "merges": [[98, 108], [256, 117]] }"#).is_ok());
+    }
+}
@@ -1,44 +1,158 @@
-use rand::{Rng, SeedableRng};
+use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 
-use crate::{color::Color, input::{Config, TrainingDataRaw}, layer::MainType, string::string_to_data};
+use crate::{augment::{augment, variant_count, SyntheticEntry}, color::{Color, ParseColorError}, input::{Config, TrainingDataRaw}, split::{self, Assignment}, string::{encode, string_to_data}};
 
-pub type GpuInputData = Vec<MainType>;
+/// The indices of the active input nodes, see `string_to_data`
+pub type GpuInputData = Vec<u32>;
 
-pub type DataSet = Vec<(GpuInputData, Color)>;
+/// The tag of the source a sample came from, see `manifest::Source`. Empty if it's unknown
+pub type Source = String;
+
+pub type DataSet = Vec<(GpuInputData, Color, Source)>;
 
 pub struct TrainingData {
     pub training: DataSet,
-    pub checking: DataSet
+    pub checking: DataSet,
+    /// The training entries which were made up by augmentation. These come after the real entries
+    pub synthetic: Vec<SyntheticEntry>,
+    /// Which set each of the real entries went in, in the order they were read
+    pub assignment: Vec<Assignment>,
 }
 
-pub fn process_data(raw: TrainingDataRaw, config: &Config) -> (TrainingData, u32) {
-    let mut output = Vec::<(GpuInputData, Color)>::default();
-    let mut truncated_data = 0;
+/// An entry which was left out because its colour couldn't be parsed
+#[derive(Debug)]
+pub struct InvalidEntry {
+    pub name: String,
+    pub source: Option<String>,
+    pub line: Option<usize>,
+    pub error: ParseColorError,
+}
 
-    for (name, color_str) in raw {
-        let mut name: &str = &name;
-        if name.len() > config.input_length_max_chars() as usize {
-            truncated_data += 1;
-            name = &name[..(config.input_length_max_chars() as usize)];
+/// Splits off the entries with colours that can't be parsed, so one bad value in a scraped list doesn't stop the training
+pub fn check_colors(raw: TrainingDataRaw) -> (TrainingDataRaw, Vec<InvalidEntry>) {
+    let mut invalid = Vec::new();
+    let valid = raw.into_iter().filter(|entry| match Color::from_str(&entry.color) {
+        Ok(_) => true,
+        Err(error) => {
+            invalid.push(InvalidEntry { name: entry.name.clone(), source: entry.source.clone(), line: entry.line, error });
+            false
         }
-        output.push((string_to_data(name, config), Color::from_str(&color_str).unwrap()));
-    }
+    }).collect();
+    return (valid, invalid);
+}
 
-    let mut training = Vec::<(GpuInputData, Color)>::default();
-    let mut checking = Vec::<(GpuInputData, Color)>::default();
-    
-    // Random number, chosen by fair dice roll
-    // (having this be deterministic should help reproducability)
-    let mut rand = ChaCha20Rng::from_seed([4; 32]);
+/// Encodes the data and splits it into the training and checking sets. The colours should've been checked by `check_colors`.
+/// Also returns the names which had to be truncated to fit the input
+pub fn process_data(raw: TrainingDataRaw, config: &Config) -> (TrainingData, Vec<String>) {
+    let mut training = Vec::<(GpuInputData, Color, Source)>::default();
+    let mut training_weights = Vec::new();
+    let mut checking = Vec::<(GpuInputData, Color, Source)>::default();
+    let mut training_names = Vec::new();
+    let mut truncated_data = Vec::new();
+    let mut assignment = Vec::new();
+
+    let colors: Vec<_> = raw.iter().map(|entry| {
+        (entry.name.clone(), Color::from_str(&entry.color).unwrap_or_else(|e| panic!("Invalid colour for {:?}: {e}", entry.name)))
+    }).collect();
+    let split = split::assign(&colors, config);
+
+    for ((entry, (_, color)), (is_training, group)) in raw.into_iter().zip(colors).zip(split) {
+        let name = entry.name;
+        let source = entry.source.unwrap_or_default();
+        let encoded = encode(&name, config);
+        if encoded.truncated {
+            truncated_data.push(name.clone());
+        }
+        assignment.push(Assignment { name: name.clone(), source: source.clone(), training: is_training, group });
 
-    for data in output {
-        if rand.gen_ratio((10000f64*config.percentage_training) as u32, 10000) {
-            training.push(data);
+        if is_training {
+            training.push((encoded.data, color, source));
+            training_weights.push(entry.weight);
+            training_names.push((name, color));
         } else {
-            checking.push(data);
+            checking.push((encoded.data, color, source));
         }
     }
 
-    (TrainingData { training, checking, }, truncated_data)
+    // Only the training set is augmented, the checking set should tell how well the network does on real names
+    let synthetic = augment(&training_names, &config.augmentation);
+    for entry in &synthetic {
+        let source = training[entry.parent].2.clone();
+        training.push((string_to_data(&entry.name, config), entry.color, source));
+        training_weights.push(training_weights[entry.parent]);
+    }
+
+    let training = apply_weights(training, &training_weights);
+    (TrainingData { training, checking, synthetic, assignment }, truncated_data)
+}
+
+/// Training uses every entry in each iteration, so an entry with weight 2 is simply used twice.
+/// Fractional weights are rounded up or down at random, so they're right on average
+fn apply_weights(data: DataSet, weights: &[f64]) -> DataSet {
+    if weights.iter().all(|w| *w == 1.0) {
+        return data;
+    }
+    // Its own seed, so weights don't change the split or the augmentation
+    let mut rand = ChaCha20Rng::from_seed([7; 32]);
+    let mut output = Vec::with_capacity(data.len());
+    for (entry, weight) in data.into_iter().zip(weights) {
+        for _ in 0..variant_count(weight.max(0.0), &mut rand) {
+            output.push(entry.clone());
+        }
+    }
+    return output;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::input::{Config, RawEntry, TrainingDataRaw};
+
+    use super::{check_colors, process_data};
+
+    fn entry(i: usize, source: &str, weight: f64) -> RawEntry {
+        RawEntry { weight, source: Some(source.to_owned()), ..RawEntry::new(format!("colour number {i}"), "#123456") }
+    }
+
+    #[test]
+    fn keeps_sources() {
+        let raw: TrainingDataRaw = (0..10).map(|i| entry(i, if i < 4 { "a" } else { "b" }, 1.0)).collect();
+        let (data, _) = process_data(raw, &Config::test(r#"{ "percentage_training": 1.0 }"#));
+        assert_eq!(data.training.iter().filter(|e| e.2 == "a").count(), 4);
+        assert_eq!(data.training.iter().filter(|e| e.2 == "b").count(), 6);
+    }
+
+    #[test]
+    fn weights_entries() {
+        let raw: TrainingDataRaw = (0..400).map(|i| match i % 4 {
+            0 => entry(i, "double", 2.0),
+            1 => entry(i, "half", 0.5),
+            2 => entry(i, "none", 0.0),
+            _ => entry(i, "normal", 1.0),
+        }).collect();
+        let (data, _) = process_data(raw, &Config::test(r#"{ "percentage_training": 1.0 }"#));
+        let count = |source: &str| data.training.iter().filter(|e| e.2 == source).count();
+        assert_eq!(count("double"), 200);
+        assert_eq!(count("normal"), 100);
+        assert_eq!(count("none"), 0);
+        assert!((30..70).contains(&count("half")), "{}", count("half"));
+    }
+
+    #[test]
+    fn skips_invalid_colors() {
+        let raw = vec![
+            RawEntry::new("red", "#ff0000"),
+            RawEntry { source: Some("scraped".to_owned()), line: Some(3), ..RawEntry::new("broken", "#ff00zz") },
+            RawEntry::new("blue", "not a colour"),
+            RawEntry::new("green", "rgb(0, 255, 0)"),
+        ];
+        let (valid, invalid) = check_colors(raw);
+        assert_eq!(valid.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["red", "green"]);
+        assert_eq!(invalid.len(), 2);
+        assert_eq!(invalid[0].name, "broken");
+        assert_eq!(invalid[0].source.as_deref(), Some("scraped"));
+        assert_eq!(invalid[0].line, Some(3));
+        assert_eq!(invalid[0].error.value, "#ff00zz");
+        assert!(invalid[1].error.to_string().starts_with("\"not a colour\" is not a colour"));
+    }
 }